@@ -0,0 +1,66 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Times parsing, expanding and rendering an outline whose examples table is far wider than
+//! usual, such as a matrix exported sideways. Run with `cargo bench --bench wide_tables`.
+
+use std::time::{Duration, Instant};
+
+use gherkin_rust::{Feature, FormatOptions, ParseOptions};
+
+const COLUMNS: usize = 800;
+const ROWS: usize = 50;
+const RUNS: u32 = 5;
+
+fn source() -> String {
+    let mut source = String::from("Feature: Matrix\n  Scenario Outline: Cell\n    Given <c0>");
+    source.push_str(" and <c799>\n\n    Examples:\n");
+
+    let row = |cell: &dyn Fn(usize) -> String| {
+        let cells: String = (0..COLUMNS).map(|c| format!(" {} |", cell(c))).collect();
+        format!("      |{}\n", cells)
+    };
+    source.push_str(&row(&|c| format!("c{}", c)));
+    for r in 0..ROWS {
+        source.push_str(&row(&|c| (r * COLUMNS + c).to_string()));
+    }
+    source
+}
+
+fn time<T>(name: &str, mut f: impl FnMut() -> T) {
+    let mut total = Duration::default();
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        std::hint::black_box(f());
+        total += start.elapsed();
+    }
+    println!("{:<10} {:>10.2?}", name, total / RUNS);
+}
+
+fn main() {
+    let source = source();
+    let feature = Feature::parse(source.clone()).unwrap();
+    let outline = &feature.scenarios[0];
+    let options = ParseOptions::default().with_max_table_columns(COLUMNS);
+
+    println!("{} columns, {} rows", COLUMNS, ROWS);
+    time("parse", || Feature::parse(source.clone()).unwrap());
+    time("limited", || {
+        Feature::parse_with_options(source.clone(), &options).unwrap()
+    });
+    time("expand", || outline.expand_examples());
+    time("lookup", || {
+        outline.examples[0]
+            .rows()
+            .filter_map(|row| row.get("c799"))
+            .count()
+    });
+    time("render", || {
+        feature.to_gherkin_string(&FormatOptions::default())
+    });
+}
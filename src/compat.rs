@@ -0,0 +1,529 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! ### Runner compatibility
+//!
+//! Flattens a feature into the scenarios a runner such as
+//! [cucumber_rust](https://github.com/bbqsrc/cucumber-rust) executes, so it need not know about
+//! rules, backgrounds or outlines, and quotes the source around a failing step with
+//! [`excerpt`]. Enabled with the `compat` feature.
+//!
+//! ```
+//! use gherkin_rust::{compat, Feature};
+//!
+//! let feature = Feature::parse(
+//!     "Feature: A\n  Background:\n    Given a step\n  Scenario: B\n    When another\n",
+//! )
+//! .unwrap();
+//! let pickles = compat::pickles(&feature);
+//! assert_eq!(pickles[0].scenario.steps.len(), 2);
+//! ```
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use crate::{Feature, ParsedFeature, Rule, Scenario, Step, Tag};
+
+/// A single run of a scenario, as returned by [`pickles`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Pickle {
+    /// The path of the feature file, if known.
+    pub path: Option<PathBuf>,
+    /// The name of the feature.
+    pub feature: String,
+    /// The name of the rule containing the scenario, if any.
+    pub rule: Option<String>,
+    /// The scenario to run. An outline is given once per examples row, with its placeholders
+    /// filled in and [`example_row`](Scenario::example_row) set. The steps of the feature's
+    /// background, then the rule's, come before its own with
+    /// [`from_background`](crate::Step::from_background) set and their positions kept, and its
+    /// tags are every tag that applies to it: the feature's, the rule's, its own and its
+    /// examples block's. Sub-steps stay under the step they belong to rather than being run as
+    /// steps of their own.
+    pub scenario: Scenario,
+}
+
+/// Every run of every scenario in `feature`, including those in rules, in document order.
+pub fn pickles(feature: &Feature) -> Vec<Pickle> {
+    let top = feature.scenarios.iter().map(|s| (None, s));
+    let nested = feature
+        .rules
+        .iter()
+        .flat_map(|r| r.scenarios().map(move |s| (Some(r), s)));
+
+    top.chain(nested)
+        .flat_map(|(rule, scenario)| {
+            // Unlike `inline_background`, the background steps keep their positions, so that
+            // failures in them can still be located.
+            let background = feature
+                .background_steps()
+                .iter()
+                .chain(rule.map_or(&[][..], |r| r.background_steps()))
+                .map(|step| Step {
+                    from_background: true,
+                    ..step.clone()
+                });
+            let mut scenario = scenario.clone();
+            scenario.steps.splice(0..0, background);
+
+            scenario
+                .expand_examples()
+                .into_iter()
+                .map(move |mut scenario| {
+                    if let Some(rule) = rule {
+                        inherit(&mut scenario, &rule.tags, &rule.tag_tokens);
+                    }
+                    inherit(&mut scenario, &feature.tags, &feature.tag_tokens);
+
+                    Pickle {
+                        path: feature.path.clone(),
+                        feature: feature.name.clone(),
+                        rule: rule.map(|r: &Rule| r.name.clone()),
+                        scenario,
+                    }
+                })
+        })
+        .collect()
+}
+
+/// How many steps either side of the failing step an [`excerpt`] shows.
+const EXCERPT_CONTEXT: usize = 2;
+
+/// Options controlling what an [`excerpt`] shows
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExcerptOptions {
+    max_width: usize,
+    step_argument: bool,
+    ansi: bool,
+}
+
+impl Default for ExcerptOptions {
+    fn default() -> Self {
+        ExcerptOptions {
+            max_width: 120,
+            step_argument: false,
+            ansi: false,
+        }
+    }
+}
+
+impl ExcerptOptions {
+    /// The most characters of a source line to show, after which it is cut short with `…`.
+    /// 120 by default.
+    pub fn with_max_width(mut self, max_width: usize) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    /// Whether to show the failing step's table or docstring. Disabled by default.
+    pub fn with_step_argument(mut self, enabled: bool) -> Self {
+        self.step_argument = enabled;
+        self
+    }
+
+    /// Whether to dim the steps around the failing one with ANSI escape codes, for a terminal.
+    /// Disabled by default.
+    pub fn with_ansi(mut self, enabled: bool) -> Self {
+        self.ansi = enabled;
+        self
+    }
+}
+
+/// A line of an [`excerpt`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExcerptLine {
+    /// The source line with this number.
+    Source(usize),
+    /// The source line with this number, of a step around the failing one.
+    Context(usize),
+    /// The failing step, underlined.
+    Failing,
+}
+
+/// The source lines around the step at `step_index` in `pickle`, one of the pickles of
+/// `document`, for reporting that the step failed, in the style of
+/// [`RustcReporter`](crate::RustcReporter):
+///
+/// ```text
+///   --> features/orders.feature:7:5
+///    |
+///  5 |   Scenario Outline: Buying <n>
+///  3 |     Given a shop
+///  6 |     When I buy <n>
+///  7 |     Then I have <n>
+///    |     ^^^^^^^^^^^^^^^
+/// 11 |     And I am happy
+///    |
+/// 14 |       | n |
+/// 16 |       | 2 |
+/// ```
+///
+/// The scenario's header is followed by the steps up to two either side of the failing one,
+/// which is underlined, in the order they run, so background steps come first. With
+/// [`ExcerptOptions::with_ansi`], the steps around it are dimmed. For a pickle of an outline,
+/// the examples table's header and the row the pickle was expanded from follow.
+///
+/// Steps with no recorded position, such as those built rather than parsed, and steps spliced
+/// in from another file by an `# include:` comment have no line of `document` to quote: a
+/// header or step around the failing one without one is left out, and such a failing step is
+/// shown as written by its [`Display`](std::fmt::Display) instead, located in the included
+/// file if it came from one.
+///
+/// Panics if `step_index` is not the index of one of the pickle's steps.
+pub fn excerpt(
+    document: &ParsedFeature,
+    pickle: &Pickle,
+    step_index: usize,
+    options: &ExcerptOptions,
+) -> String {
+    let scenario = &pickle.scenario;
+    let step = &scenario.steps[step_index];
+    let lines: Vec<_> = document
+        .source
+        .split('\n')
+        .map(|l| l.trim_end_matches('\r'))
+        .collect();
+
+    // Steps spliced in by an `# include:` comment have positions in the included file.
+    let in_document = |s: &Step| s.position.0 > 0 && s.origin.is_none();
+
+    let mut shown: Vec<_> = Some(scenario.position.0)
+        .filter(|&n| n > 0)
+        .map(ExcerptLine::Source)
+        .into_iter()
+        .collect();
+    let first = step_index.saturating_sub(EXCERPT_CONTEXT);
+    let last = (step_index + EXCERPT_CONTEXT).min(scenario.steps.len() - 1);
+    for (i, s) in scenario.steps.iter().enumerate().take(last + 1).skip(first) {
+        if i == step_index {
+            shown.push(ExcerptLine::Failing);
+            if options.step_argument && in_document(step) {
+                shown.extend(
+                    argument_lines(document, step)
+                        .into_iter()
+                        .map(ExcerptLine::Source),
+                );
+            }
+        } else if in_document(s) {
+            shown.push(ExcerptLine::Context(s.position.0));
+        }
+    }
+    let mut row = vec![];
+    if let Some((line, _)) = scenario.example_row {
+        row.extend(examples_header(document, scenario, line));
+        row.push(line);
+    }
+
+    let width = shown
+        .iter()
+        .map(|l| match *l {
+            ExcerptLine::Source(n) | ExcerptLine::Context(n) => n,
+            ExcerptLine::Failing if in_document(step) => step.position.0,
+            ExcerptLine::Failing => 0,
+        })
+        .chain(row.iter().copied())
+        .max()
+        .map_or(1, |n| n.to_string().len());
+    let gutter = " ".repeat(width);
+    let cut = |line: &str| match line.char_indices().nth(options.max_width) {
+        Some((i, _)) => format!("{}…", &line[..i]),
+        None => line.to_string(),
+    };
+    let source_line = |out: &mut String, number: usize, dim: bool| {
+        let line = cut(lines.get(number.wrapping_sub(1)).copied().unwrap_or(""));
+        let line = if dim && options.ansi {
+            format!("\u{1b}[2m{}\u{1b}[22m", line)
+        } else {
+            line
+        };
+        writeln!(out, "{:>width$} | {}", number, line, width = width).unwrap();
+    };
+
+    let mut out = String::new();
+    let (line, column) = step.position;
+    let mut location: Vec<_> = match &step.origin {
+        Some(origin) => vec![origin.clone()],
+        None => pickle
+            .path
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect(),
+    };
+    if line > 0 {
+        location.push(line.to_string());
+        location.push(column.to_string());
+    }
+    if location.is_empty() {
+        writeln!(out, "{}-->", gutter).unwrap();
+    } else {
+        writeln!(out, "{}--> {}", gutter, location.join(":")).unwrap();
+    }
+    writeln!(out, "{} |", gutter).unwrap();
+
+    for shown in shown {
+        match shown {
+            ExcerptLine::Source(number) => source_line(&mut out, number, false),
+            ExcerptLine::Context(number) => source_line(&mut out, number, true),
+            ExcerptLine::Failing => {
+                let (indent, len) = if in_document(step) {
+                    source_line(&mut out, line, false);
+                    // Tabs are kept in the carets' indentation so they line up however they
+                    // are displayed.
+                    let before = lines
+                        .get(line - 1)
+                        .and_then(|l| l.get(..column.saturating_sub(1)))
+                        .unwrap_or("");
+                    let indent: String = before
+                        .chars()
+                        .map(|c| if c == '\t' { '\t' } else { ' ' })
+                        .collect();
+                    (indent, document.text_of(step.span).chars().count())
+                } else {
+                    let text = step.to_string();
+                    writeln!(out, "{} | {}", gutter, cut(&text)).unwrap();
+                    (String::new(), text.chars().count())
+                };
+                let carets = len.min(options.max_width.saturating_sub(indent.chars().count()));
+                writeln!(out, "{} | {}{}", gutter, indent, "^".repeat(carets.max(1))).unwrap();
+            }
+        }
+    }
+
+    if !row.is_empty() {
+        writeln!(out, "{} |", gutter).unwrap();
+        for number in row {
+            source_line(&mut out, number, false);
+        }
+    }
+    out
+}
+
+/// The numbers of the lines of the table or docstring under `step`.
+fn argument_lines(document: &ParsedFeature, step: &Step) -> Vec<usize> {
+    let line_of = |offset: usize| {
+        document
+            .source
+            .get(..offset)
+            .map_or(0, |s| s.matches('\n').count() + 1)
+    };
+    let table = step
+        .table
+        .iter()
+        .flat_map(|t| t.row_positions.iter().map(|p| p.0))
+        .filter(|&n| n > 0);
+    let docstring = step
+        .docstring_span
+        .filter(|&(start, end)| end > start)
+        .map_or(0..0, |(start, end)| line_of(start)..line_of(end) + 1);
+
+    table.chain(docstring).collect()
+}
+
+/// The line of the header row of the examples table that `scenario` was expanded from the row
+/// at `line` of.
+fn examples_header(document: &ParsedFeature, scenario: &Scenario, line: usize) -> Option<usize> {
+    document
+        .feature
+        .all_scenarios()
+        .filter(|s| s.position == scenario.position)
+        .flat_map(|s| s.examples.iter())
+        .filter_map(|e| e.table.as_ref())
+        .find(|t| t.row_positions.iter().skip(1).any(|p| p.0 == line))
+        .and_then(|t| t.row_positions.first())
+        .map(|p| p.0)
+}
+
+/// Places the tags not already on `scenario` before its own, keeping `tag_tokens` parallel.
+fn inherit(scenario: &mut Scenario, tags: &[String], tokens: &[Tag]) {
+    for (i, tag) in tags.iter().enumerate().rev() {
+        if !scenario.tags.contains(tag) {
+            scenario.tags.insert(0, tag.clone());
+            if let Some(token) = tokens.get(i) {
+                scenario.tag_tokens.insert(0, token.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattens_rules_backgrounds_and_outlines() {
+        let feature = Feature::parse(
+            r"@shop
+Feature: Orders
+  Background:
+    Given a shop
+
+  Scenario Outline: Buying <n>
+    When I buy <n>
+
+    @small
+    Examples:
+      | n |
+      | 1 |
+      | 2 |
+
+  @refunds
+  Rule: Refunds
+    Background:
+      Given an order
+
+    @slow
+    Scenario: Refunding
+      When I refund it
+",
+        )
+        .unwrap();
+
+        let pickles = pickles(&feature);
+        let summary: Vec<_> = pickles
+            .iter()
+            .map(|p| {
+                let steps: Vec<_> = p.scenario.steps.iter().map(|s| &*s.value).collect();
+                (
+                    p.rule.as_deref(),
+                    &*p.scenario.name,
+                    p.scenario.tags.iter().map(|t| &**t).collect::<Vec<_>>(),
+                    steps,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    None,
+                    "Buying 1",
+                    vec!["shop", "small"],
+                    vec!["a shop", "I buy 1"]
+                ),
+                (
+                    None,
+                    "Buying 2",
+                    vec!["shop", "small"],
+                    vec!["a shop", "I buy 2"]
+                ),
+                (
+                    Some("Refunds"),
+                    "Refunding",
+                    vec!["shop", "refunds", "slow"],
+                    vec!["a shop", "an order", "I refund it"]
+                ),
+            ]
+        );
+        assert!(pickles.iter().all(|p| p.feature == "Orders"));
+        assert!(pickles[2].scenario.steps[1].from_background);
+        assert_eq!(pickles[2].scenario.tag_tokens.len(), 3);
+        assert_eq!(pickles[1].scenario.example_row, Some((13, 7)));
+    }
+
+    #[test]
+    fn excerpts_underline_the_failing_step() {
+        let source = r#"Feature: Orders
+  Background:
+    Given a shop
+
+  Scenario Outline: Buying <n>
+    When I buy <n>
+    Then I have <n>
+      """
+      <n> items
+      """
+    And I am happy
+
+    Examples:
+      | n |
+      | 1 |
+      | 2 |
+"#;
+        let mut document = Feature::parse_with_source(source.to_string()).unwrap();
+        document.feature.path = Some(PathBuf::from("features/orders.feature"));
+        let pickle = &pickles(&document.feature)[1];
+
+        assert_eq!(
+            excerpt(&document, pickle, 2, &ExcerptOptions::default()),
+            r#"  --> features/orders.feature:7:5
+   |
+ 5 |   Scenario Outline: Buying <n>
+ 3 |     Given a shop
+ 6 |     When I buy <n>
+ 7 |     Then I have <n>
+   |     ^^^^^^^^^^^^^^^
+11 |     And I am happy
+   |
+14 |       | n |
+16 |       | 2 |
+"#
+        );
+
+        let options = ExcerptOptions::default()
+            .with_step_argument(true)
+            .with_max_width(12);
+        assert_eq!(
+            excerpt(&document, pickle, 2, &options),
+            r#"  --> features/orders.feature:7:5
+   |
+ 5 |   Scenario O…
+ 3 |     Given a …
+ 6 |     When I b…
+ 7 |     Then I h…
+   |     ^^^^^^^^
+ 8 |       """
+ 9 |       <n> it…
+10 |       """
+11 |     And I am…
+   |
+14 |       | n |
+16 |       | 2 |
+"#
+        );
+    }
+
+    #[test]
+    fn excerpts_dim_context_and_quote_positionless_steps() {
+        let source = "Feature: Orders\n  Scenario: Buying\n    Given a shop\n    When I buy one\n    Then I have one\n";
+        let document = Feature::parse_with_source(source.to_string()).unwrap();
+        let mut pickle = pickles(&document.feature).remove(0);
+
+        assert_eq!(
+            excerpt(
+                &document,
+                &pickle,
+                1,
+                &ExcerptOptions::default().with_ansi(true)
+            ),
+            " --> 4:5\n  |\n2 |   Scenario: Buying\n3 | \u{1b}[2m    Given a shop\u{1b}[22m\n4 |     When I buy one\n  |     ^^^^^^^^^^^^^^\n5 | \u{1b}[2m    Then I have one\u{1b}[22m\n"
+        );
+
+        pickle.scenario.steps[0].position = (0, 0);
+        pickle.scenario.steps[1].position = (0, 0);
+        assert_eq!(
+            excerpt(&document, &pickle, 1, &ExcerptOptions::default()),
+            " -->\n  |\n2 |   Scenario: Buying\n  | When I buy one\n  | ^^^^^^^^^^^^^^\n5 |     Then I have one\n"
+        );
+    }
+
+    #[test]
+    fn excerpts_locate_included_steps_in_their_file() {
+        let options = crate::ParseOptions::default().with_include_resolver(|_: &str| {
+            Ok("\n\n\n\n\n\n\n\n\nBackground:\n    Given the site is up\n".to_string())
+        });
+        let source = "# include: common/site.background\nFeature: Site\n  Scenario: Visiting\n    When I visit\n";
+        let document = Feature::parse_with_options(source.to_string(), &options).unwrap();
+        let pickle = &pickles(&document.feature)[0];
+
+        assert_eq!(
+            excerpt(&document, pickle, 0, &ExcerptOptions::default()),
+            " --> common/site.background:11:5\n  |\n3 |   Scenario: Visiting\n  | Given the site is up\n  | ^^^^^^^^^^^^^^^^^^^^\n4 |     When I visit\n"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! ### Grammar coverage
+//!
+//! Reports which rules of the grammar a set of sources exercises, so a test corpus can be
+//! checked to cover all of them before the grammar is changed. Enabled with the `test-utils`
+//! feature.
+//!
+//! The rules are recorded by the parser itself as they match, and forgotten again when it
+//! backtracks over them. Only the rules producing the constructs listed in [`GrammarRule`] are
+//! recorded, along with those recovering from or reporting mistakes, not the many smaller
+//! rules for keywords, whitespace and the like. Rules that only match in input that fails to
+//! parse are covered by [`error_coverage`].
+//!
+//! ```
+//! use gherkin_rust::coverage::{grammar_coverage, GrammarRule};
+//!
+//! let covered = grammar_coverage(&["Feature: A\n  Scenario: B\n    Given a step\n"]).unwrap();
+//! assert!(covered.contains(&GrammarRule::Step));
+//! assert!(!covered.contains(&GrammarRule::Table));
+//! ```
+
+use std::collections::BTreeSet;
+
+use crate::parser::{self, GherkinEnv};
+use crate::{LineCol, ParseError, ParseOptions};
+
+/// A rule of the grammar producing a construct of a .feature file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GrammarRule {
+    Comment,
+    LanguageDirective,
+    Feature,
+    FeatureTags,
+    Description,
+    Background,
+    Rule,
+    RuleTags,
+    RuleBackground,
+    Scenario,
+    ScenarioTags,
+    ScenarioOutline,
+    Examples,
+    ExamplesTags,
+    Step,
+    /// A step starting with a keyword that continues the previous step's type, such as `And`.
+    ContinuationStep,
+    Docstring,
+    BacktickDocstring,
+    /// A docstring that is never closed, taken to end before the next step or block.
+    UnclosedDocstring,
+    /// A line that a docstring's content cannot reach, showing it was never closed.
+    DocstringOverrun,
+    Table,
+    /// A comment after the last `|` of a table row.
+    RowComment,
+    /// A table row with text outside its cells, which is skipped.
+    BrokenTableRow,
+    /// A docstring in place of an examples table. This always fails the parse.
+    ExamplesDocstring,
+}
+
+impl GrammarRule {
+    /// Every rule, in declaration order.
+    pub const ALL: &'static [GrammarRule] = &[
+        GrammarRule::Comment,
+        GrammarRule::LanguageDirective,
+        GrammarRule::Feature,
+        GrammarRule::FeatureTags,
+        GrammarRule::Description,
+        GrammarRule::Background,
+        GrammarRule::Rule,
+        GrammarRule::RuleTags,
+        GrammarRule::RuleBackground,
+        GrammarRule::Scenario,
+        GrammarRule::ScenarioTags,
+        GrammarRule::ScenarioOutline,
+        GrammarRule::Examples,
+        GrammarRule::ExamplesTags,
+        GrammarRule::Step,
+        GrammarRule::ContinuationStep,
+        GrammarRule::Docstring,
+        GrammarRule::BacktickDocstring,
+        GrammarRule::UnclosedDocstring,
+        GrammarRule::DocstringOverrun,
+        GrammarRule::Table,
+        GrammarRule::RowComment,
+        GrammarRule::BrokenTableRow,
+        GrammarRule::ExamplesDocstring,
+    ];
+
+    /// The name the parser records the rule under when it matches.
+    fn name(self) -> &'static str {
+        match self {
+            GrammarRule::Comment => "comment",
+            GrammarRule::LanguageDirective => "language_directive",
+            GrammarRule::Feature => "feature",
+            GrammarRule::FeatureTags => "feature_tags",
+            GrammarRule::Description => "description",
+            GrammarRule::Background => "background",
+            GrammarRule::Rule => "rule",
+            GrammarRule::RuleTags => "rule_tags",
+            GrammarRule::RuleBackground => "rule_background",
+            GrammarRule::Scenario => "scenario",
+            GrammarRule::ScenarioTags => "scenario_tags",
+            GrammarRule::ScenarioOutline => "scenario_outline",
+            GrammarRule::Examples => "examples",
+            GrammarRule::ExamplesTags => "examples_tags",
+            GrammarRule::Step => "step",
+            GrammarRule::ContinuationStep => "continuation_step",
+            GrammarRule::Docstring => "docstring",
+            GrammarRule::BacktickDocstring => "backtick_docstring",
+            GrammarRule::UnclosedDocstring => "unclosed_docstring",
+            GrammarRule::DocstringOverrun => "docstring_overrun",
+            GrammarRule::Table => "table",
+            GrammarRule::RowComment => "row_comment",
+            GrammarRule::BrokenTableRow => "broken_table_row",
+            GrammarRule::ExamplesDocstring => "examples_docstring",
+        }
+    }
+}
+
+/// The rules that matched at least once while parsing each of `sources`, or the first parse
+/// error.
+pub fn grammar_coverage<I, S>(sources: I) -> Result<BTreeSet<GrammarRule>, ParseError<LineCol>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let options = ParseOptions::default();
+    let mut hits = BTreeSet::new();
+
+    for source in sources {
+        let input = parser::prepare(source.as_ref(), &options);
+        let env = GherkinEnv::with_options(&input, options.clone());
+        parser::parse(&input, &env)?;
+        hits.extend(env.hits());
+    }
+
+    Ok(covered(&hits))
+}
+
+/// The rules that matched at least once while parsing each of `sources`, up to where the parse
+/// failed if it did. This covers the rules that report mistakes, which only match in input that
+/// fails to parse.
+pub fn error_coverage<I, S>(sources: I) -> BTreeSet<GrammarRule>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let options = ParseOptions::default();
+    let mut hits = BTreeSet::new();
+
+    for source in sources {
+        let input = parser::prepare(source.as_ref(), &options);
+        let env = GherkinEnv::with_options(&input, options.clone());
+        let _ = parser::parse(&input, &env);
+        hits.extend(env.hits());
+    }
+
+    covered(&hits)
+}
+
+fn covered(hits: &BTreeSet<&'static str>) -> BTreeSet<GrammarRule> {
+    GrammarRule::ALL
+        .iter()
+        .copied()
+        .filter(|rule| hits.contains(rule.name()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures_in(dir: &str) -> Vec<String> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+        std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == "feature"))
+            .map(|p| std::fs::read_to_string(p).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn fixtures_cover_every_rule() {
+        let sources = fixtures_in("tests/fixtures");
+        let invalid = fixtures_in("tests/fixtures/invalid");
+        for source in &invalid {
+            assert!(crate::Feature::parse(source).is_err(), "{}", source);
+        }
+
+        let mut covered = grammar_coverage(&sources).unwrap_or_else(|e| panic!("{}", e));
+        covered.extend(error_coverage(&invalid));
+        let missing: Vec<_> = GrammarRule::ALL
+            .iter()
+            .filter(|r| !covered.contains(r))
+            .collect();
+        assert!(missing.is_empty(), "not covered by fixtures: {:?}", missing);
+    }
+
+    #[test]
+    fn rules_backtracked_over_are_not_covered() {
+        // The step with a table fails for want of a type, so only the scenario's title matches.
+        let source = "Feature: A\n  Scenario: B\n    And c\n      | d |\n";
+        let input = parser::prepare(source, &ParseOptions::default());
+        let env = GherkinEnv::new(&input);
+        assert!(parser::parse(&input, &env).is_err());
+
+        let hits = env.hits();
+        assert!(hits.contains(&"scenario"));
+        assert!(!hits.contains(&"table"));
+        for hit in hits {
+            assert!(GrammarRule::ALL.iter().any(|r| r.name() == hit), "{}", hit);
+        }
+    }
+}
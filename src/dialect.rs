@@ -0,0 +1,347 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::{Step, StepType};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Keywords<'a> {
+    pub(crate) feature: &'a [&'a str],
+    pub(crate) background: &'a [&'a str],
+    pub(crate) rule: &'a [&'a str],
+    pub(crate) scenario: &'a [&'a str],
+    pub(crate) scenario_outline: &'a [&'a str],
+    pub(crate) examples: &'a [&'a str],
+    pub(crate) given: &'a [&'a str],
+    pub(crate) when: &'a [&'a str],
+    pub(crate) then: &'a [&'a str],
+    pub(crate) and: &'a [&'a str],
+    pub(crate) but: &'a [&'a str],
+}
+
+impl<'a> Keywords<'a> {
+    pub fn all(&self) -> Vec<&'a str> {
+        let mut v = vec![];
+
+        for x in [
+            self.feature,
+            self.background,
+            self.rule,
+            self.scenario,
+            self.rule,
+            self.scenario_outline,
+            self.examples,
+            self.given,
+            self.when,
+            self.then,
+            self.and,
+            self.but,
+        ]
+        .iter()
+        {
+            v.append(&mut x.to_vec());
+        }
+
+        v
+    }
+
+    /// The keywords that start a block other than the feature itself.
+    pub(crate) fn blocks(&self) -> Vec<&'a str> {
+        [
+            self.background,
+            self.rule,
+            self.scenario,
+            self.scenario_outline,
+            self.examples,
+        ]
+        .concat()
+    }
+}
+
+const DEFAULT_KEYWORDS: Keywords<'static> = Keywords {
+    feature: &["Feature"],
+    background: &["Background"],
+    rule: &["Rule"],
+    scenario: &["Scenario", "Example"],
+    scenario_outline: &["Scenario Outline", "Scenario Template"],
+    examples: &["Examples"],
+    given: &["Given"],
+    when: &["When"],
+    then: &["Then"],
+    and: &["*", "And"],
+    but: &["But"],
+};
+
+const FORMAL_SPEC_KEYWORDS: Keywords<'static> = Keywords {
+    feature: &["Section"],
+    background: &["Context"],
+    rule: &["Rule"],
+    scenario: &["Proof", "Evidence"],
+    scenario_outline: &["Demonstration"],
+    examples: &["Examples"],
+    given: &["Given"],
+    when: &["When"],
+    then: &["Then"],
+    and: &["*", "And"],
+    but: &["But"],
+};
+
+const SV_KEYWORDS: Keywords<'static> = Keywords {
+    feature: &["Egenskap"],
+    background: &["Bakgrund"],
+    rule: &["Regel"],
+    scenario: &["Scenario", "Exempel"],
+    scenario_outline: &["Abstrakt Scenario"],
+    examples: &["Exempel"],
+    given: &["Givet"],
+    when: &["När"],
+    then: &["Så"],
+    and: &["*", "Och"],
+    but: &["Men"],
+};
+
+const DE_KEYWORDS: Keywords<'static> = Keywords {
+    feature: &["Funktionalität", "Funktion"],
+    background: &[
+        "Grundlage",
+        "Hintergrund",
+        "Voraussetzungen",
+        "Vorbedingungen",
+    ],
+    rule: &["Regel", "Rule"],
+    scenario: &["Szenario", "Beispiel"],
+    scenario_outline: &["Szenariogrundriss", "Szenarien"],
+    examples: &["Beispiele"],
+    given: &["Angenommen", "Gegeben sei", "Gegeben seien"],
+    when: &["Wenn"],
+    then: &["Dann"],
+    and: &["*", "Und"],
+    but: &["Aber"],
+};
+
+/// The longest keyword in `list` that `input` starts with.
+pub(crate) fn find_keyword(
+    list: &[&'static str],
+    input: &str,
+    ignore_case: bool,
+) -> Option<&'static str> {
+    list.iter()
+        .copied()
+        .filter(|x| {
+            if ignore_case {
+                let mut chars = input.chars();
+                x.chars().all(|a| {
+                    chars
+                        .next()
+                        .is_some_and(|b| a.to_lowercase().eq(b.to_lowercase()))
+                })
+            } else {
+                input.starts_with(x)
+            }
+        })
+        .max_by_key(|x| x.len())
+}
+
+/// The keywords of one of the languages selectable with a `# language:` directive
+#[derive(Debug, Clone, Copy)]
+pub struct Dialect {
+    language: &'static str,
+    pub(crate) keywords: Keywords<'static>,
+}
+
+impl Dialect {
+    /// The dialect for a language code such as `en` or `de`, if supported.
+    pub fn from_language(language: &str) -> Option<Dialect> {
+        let (language, keywords) = match language {
+            "en" => ("en", DEFAULT_KEYWORDS),
+            "de" => ("de", DE_KEYWORDS),
+            "sv" => ("sv", SV_KEYWORDS),
+            "formal" => ("formal", FORMAL_SPEC_KEYWORDS),
+            _ => return None,
+        };
+
+        Some(Dialect { language, keywords })
+    }
+
+    /// The language code of the dialect.
+    pub fn language(&self) -> &'static str {
+        self.language
+    }
+
+    /// Whether the language is also provided by the reference implementation.
+    pub(crate) fn is_standard(&self) -> bool {
+        self.language != "formal"
+    }
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect {
+            language: "en",
+            keywords: DEFAULT_KEYWORDS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum StepKeywordError {
+    #[error("Not a step keyword in this dialect: {0}")]
+    Unknown(String),
+
+    #[error("No preceding step to continue with: {0}")]
+    MissingContext(String),
+
+    #[error("Step keyword {0} cannot start a {1:?} step")]
+    Mismatch(String, StepType),
+}
+
+impl StepType {
+    /// Resolves a step keyword of `dialect` the same way the parser does. `And`, `But` and `*`
+    /// (and their translations) take the type of the preceding step, given as `context`.
+    pub fn from_keyword(
+        keyword: &str,
+        dialect: &Dialect,
+        context: Option<StepType>,
+    ) -> Result<StepType, StepKeywordError> {
+        let keywords = &dialect.keywords;
+        let keyword = keyword.trim();
+
+        if keywords.given.contains(&keyword) {
+            Ok(StepType::Given)
+        } else if keywords.when.contains(&keyword) {
+            Ok(StepType::When)
+        } else if keywords.then.contains(&keyword) {
+            Ok(StepType::Then)
+        } else if keywords.and.contains(&keyword) || keywords.but.contains(&keyword) {
+            context.ok_or_else(|| StepKeywordError::MissingContext(keyword.to_string()))
+        } else {
+            Err(StepKeywordError::Unknown(keyword.to_string()))
+        }
+    }
+}
+
+impl Step {
+    /// Checks that `raw_type` is a step keyword of `dialect`, ignoring case, that can start a
+    /// step of type `ty`: either a keyword of that type or a conjunction such as `And`, `But`
+    /// or `*`, which continues any type.
+    ///
+    /// Each of the step's sub-steps is then checked in the same way.
+    ///
+    /// Steps from the parser always pass; this is for steps built or edited by hand.
+    pub fn validate(&self, dialect: &Dialect) -> Result<(), StepKeywordError> {
+        let keywords = &dialect.keywords;
+        let all = [
+            keywords.given,
+            keywords.when,
+            keywords.then,
+            keywords.and,
+            keywords.but,
+        ]
+        .concat();
+        let raw_type = self.raw_type.trim();
+        let keyword = find_keyword(&all, raw_type, true)
+            .filter(|k| k.chars().count() == raw_type.chars().count())
+            .ok_or_else(|| StepKeywordError::Unknown(raw_type.to_string()))?;
+
+        if StepType::from_keyword(keyword, dialect, Some(self.ty))? != self.ty {
+            return Err(StepKeywordError::Mismatch(raw_type.to_string(), self.ty));
+        }
+
+        self.sub_steps.iter().try_for_each(|s| s.validate(dialect))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Feature;
+
+    #[test]
+    fn resolves_localized_step_keywords() {
+        let de = Dialect::from_language("de").unwrap();
+        assert_eq!(de.language(), "de");
+        assert_eq!(
+            StepType::from_keyword("Und", &de, Some(StepType::When)),
+            Ok(StepType::When)
+        );
+        assert_eq!(
+            StepType::from_keyword("Gegeben sei", &de, None),
+            Ok(StepType::Given)
+        );
+        assert_eq!(
+            StepType::from_keyword("Aber", &de, None),
+            Err(StepKeywordError::MissingContext("Aber".to_string()))
+        );
+        assert_eq!(
+            StepType::from_keyword("When", &de, None),
+            Err(StepKeywordError::Unknown("When".to_string()))
+        );
+    }
+
+    #[test]
+    fn validates_step_keywords() {
+        let step = |ty, raw_type: &str| {
+            Step::builder()
+                .ty(ty)
+                .raw_type(raw_type.to_string())
+                .value("a step".to_string())
+                .build()
+        };
+        let en = Dialect::default();
+
+        assert_eq!(step(StepType::Then, "Then").validate(&en), Ok(()));
+        assert_eq!(step(StepType::Then, "and").validate(&en), Ok(()));
+        assert_eq!(step(StepType::When, "*").validate(&en), Ok(()));
+        assert_eq!(
+            step(StepType::Then, "Given").validate(&en),
+            Err(StepKeywordError::Mismatch(
+                "Given".to_string(),
+                StepType::Then
+            ))
+        );
+        assert_eq!(
+            step(StepType::Given, "Givens").validate(&en),
+            Err(StepKeywordError::Unknown("Givens".to_string()))
+        );
+
+        let de = Dialect::from_language("de").unwrap();
+        assert_eq!(step(StepType::Given, "Angenommen").validate(&de), Ok(()));
+    }
+
+    #[test]
+    fn parses_non_ascii_keywords() {
+        let feature = Feature::parse(
+            "# language: de
+Funktionalität: Gurken
+  Szenariogrundriss: Essen
+    Gegeben seien <n> Gurken
+    Wenn ich eine esse
+    Und noch eine
+    Dann bleiben weniger übrig
+
+    Beispiele:
+      | n |
+      | 5 |
+",
+        )
+        .unwrap();
+        let steps = &feature.scenarios[0].steps;
+
+        assert_eq!(feature.name, "Gurken");
+        assert_eq!(steps[0].raw_type, "Gegeben seien");
+        assert_eq!(steps[0].value, "<n> Gurken");
+        assert_eq!(steps[2].ty, StepType::When);
+        assert_eq!(feature.scenarios[0].examples.len(), 1);
+
+        let feature = Feature::parse(
+            "# language: sv\nEgenskap: Gurkor\n  Scenario: Äta\n    När jag äter\n    Så är jag mätt\n",
+        )
+        .unwrap();
+        assert_eq!(feature.scenarios[0].steps[0].value, "jag äter");
+        assert_eq!(feature.scenarios[0].steps[1].ty, StepType::Then);
+    }
+}
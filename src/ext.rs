@@ -0,0 +1,417 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Feature, Rule, Scenario, ScenarioParent, Step, Table};
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for crate::Feature {}
+    impl Sealed for crate::Scenario {}
+    impl Sealed for crate::Step {}
+    impl Sealed for crate::Table {}
+}
+
+/// Convenience methods for [`Feature`]
+///
+/// The extension traits hold the methods that only read and summarize what is already in a
+/// node's public fields, such as counts, lists of names and tags, and lookups. The types' own
+/// methods parse, render, walk, transform, identify and check them.
+///
+/// Like the other extension traits, this is sealed so that methods can be added to it
+/// without a breaking change. Import it with [`prelude`](crate::prelude).
+pub trait FeatureExt: sealed::Sealed {
+    /// The number of scenarios in the feature, including those nested in rules.
+    fn scenario_count(&self) -> usize;
+
+    /// The name of each scenario that would run, in document order, for a dry run listing: an
+    /// outline gives one name per examples row, with its placeholders filled in. The
+    /// background does not run on its own, so is not listed.
+    fn as_pickle_names(&self) -> Vec<String>;
+
+    /// The index of `scenario` among the feature's scenarios, including those nested in rules,
+    /// in document order.
+    ///
+    /// The scenario is found by identity or, failing that, by name and position, so a clone of
+    /// one of the feature's scenarios is found too.
+    fn scenario_index(&self, scenario: &Scenario) -> Option<usize>;
+
+    /// The names of every scenario in the feature, including those nested in rules, in
+    /// document order.
+    fn scenario_names(&self) -> Vec<&str>;
+
+    /// How many times each distinct step value appears in the feature, counting the background,
+    /// scenarios and outline templates, to help find steps worth sharing.
+    fn step_frequency(&self) -> BTreeMap<String, usize>;
+
+    /// The number of steps run for the whole feature, as in the scenarios given by
+    /// [`Scenario::expand_examples`].
+    ///
+    /// Each scenario, including those in rules, contributes its own steps plus the background's
+    /// steps, multiplied by the number of times it runs: once per data row of its examples, or
+    /// once if it has none. An outline whose examples have no data rows does not run at all.
+    fn total_step_count(&self) -> usize;
+
+    /// The tags that apply to `scenario`: the feature's, then those of the rule containing it (if
+    /// any), then the scenario's own, without duplicates.
+    ///
+    /// The containing rule is found by identity, so `scenario` must be borrowed from this feature
+    /// for rule tags to be included.
+    fn effective_tags<'a>(&'a self, scenario: &'a Scenario) -> Vec<&'a str>;
+
+    /// The rule directly containing `scenario`, or the feature if it is not in a rule. The
+    /// scenario is found by identity, so `None` is returned if it is not borrowed from this
+    /// feature.
+    fn parent_of(&self, scenario: &Scenario) -> Option<ScenarioParent<'_>>;
+
+    /// How many scenarios, including those nested in rules, each tag applies to, counting the
+    /// tags inherited from the feature and rule as [`effective_tags`](FeatureExt::effective_tags)
+    /// does.
+    fn count_by_tag(&self) -> BTreeMap<String, usize>;
+
+    /// The [`count_by_tag`](FeatureExt::count_by_tag) of each of `features` summed together, such
+    /// as for every feature loaded from a directory.
+    fn tag_histogram(features: &[Feature]) -> BTreeMap<String, usize>;
+
+    /// Every distinct tag used anywhere in the feature: on the feature itself, its rules,
+    /// scenarios and examples.
+    fn tag_set(&self) -> BTreeSet<&str>;
+
+    /// The scenario named by `line` in a rerun file, as given by [`ScenarioExt::source_line`]: the
+    /// scenario starting on that line, or the scenario expanded from the examples row on it.
+    fn scenario_or_row_at_line(&self, line: usize) -> Option<Scenario>;
+
+    /// The summed `step_weight` of every step run for the feature, counted as by
+    /// [`total_step_count`](FeatureExt::total_step_count). Outline steps are weighed with their
+    /// placeholders filled in for each row.
+    fn weighted_cost(&self, step_weight: impl Fn(&Step) -> f64) -> f64;
+}
+
+impl FeatureExt for Feature {
+    fn scenario_count(&self) -> usize {
+        self.all_scenarios().count()
+    }
+
+    fn as_pickle_names(&self) -> Vec<String> {
+        self.all_scenarios()
+            .flat_map(Scenario::iter_expansions)
+            .map(|s| s.name)
+            .collect()
+    }
+
+    fn scenario_index(&self, scenario: &Scenario) -> Option<usize> {
+        let scenarios = self.ordered_scenarios(|_| None);
+
+        scenarios
+            .iter()
+            .position(|s| std::ptr::eq(*s, scenario))
+            .or_else(|| {
+                scenarios
+                    .iter()
+                    .position(|s| s.name == scenario.name && s.position == scenario.position)
+            })
+    }
+
+    fn scenario_names(&self) -> Vec<&str> {
+        self.all_scenarios().map(|s| &*s.name).collect()
+    }
+
+    fn step_frequency(&self) -> BTreeMap<String, usize> {
+        let mut frequency = BTreeMap::new();
+
+        for step in self.all_steps() {
+            *frequency.entry(step.value.clone()).or_insert(0) += 1;
+        }
+
+        frequency
+    }
+
+    fn total_step_count(&self) -> usize {
+        self.all_scenarios()
+            .map(|s| s.step_count(true, self) * s.run_count())
+            .sum()
+    }
+
+    fn effective_tags<'a>(&'a self, scenario: &'a Scenario) -> Vec<&'a str> {
+        let rule_tags = self.rule_of(scenario).map(|r| &*r.tags).unwrap_or(&[]);
+        let mut tags: Vec<&str> = vec![];
+
+        for tag in self
+            .tags
+            .iter()
+            .chain(rule_tags)
+            .chain(scenario.tags.iter())
+        {
+            if !tags.contains(&&**tag) {
+                tags.push(tag);
+            }
+        }
+
+        tags
+    }
+
+    fn parent_of(&self, scenario: &Scenario) -> Option<ScenarioParent<'_>> {
+        if self.scenarios.iter().any(|s| std::ptr::eq(s, scenario)) {
+            return Some(ScenarioParent::Feature(self));
+        }
+        self.rule_of(scenario).map(ScenarioParent::Rule)
+    }
+
+    fn count_by_tag(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+
+        for scenario in self.all_scenarios() {
+            for tag in self.effective_tags(scenario) {
+                *counts.entry(tag.to_string()).or_insert(0) += 1;
+            }
+        }
+
+        counts
+    }
+
+    fn tag_histogram(features: &[Feature]) -> BTreeMap<String, usize> {
+        let mut histogram = BTreeMap::new();
+
+        for feature in features {
+            for (tag, count) in feature.count_by_tag() {
+                *histogram.entry(tag).or_insert(0) += count;
+            }
+        }
+
+        histogram
+    }
+
+    fn tag_set(&self) -> BTreeSet<&str> {
+        let mut set: BTreeSet<&str> = self.tags.iter().map(|t| &**t).collect();
+        set.extend(self.rules.iter().flat_map(|r| r.tags.iter()).map(|t| &**t));
+
+        for scenario in self.all_scenarios() {
+            set.extend(scenario.tags.iter().map(|t| &**t));
+            set.extend(
+                scenario
+                    .examples
+                    .iter()
+                    .flat_map(|e| e.tags.iter())
+                    .map(|t| &**t),
+            );
+        }
+
+        set
+    }
+
+    fn scenario_or_row_at_line(&self, line: usize) -> Option<Scenario> {
+        self.all_scenarios().find_map(|scenario| {
+            if scenario.position.0 == line {
+                Some(scenario.clone())
+            } else {
+                scenario
+                    .expand_examples()
+                    .into_iter()
+                    .find(|s| s.example_row.is_some_and(|p| p.0 == line))
+            }
+        })
+    }
+
+    fn weighted_cost(&self, step_weight: impl Fn(&Step) -> f64) -> f64 {
+        let weigh = |steps: &[Step]| steps.iter().map(&step_weight).sum::<f64>();
+        let background = weigh(self.background_steps());
+
+        self.all_scenarios()
+            .flat_map(|scenario| {
+                let rule = self
+                    .rule_of(scenario)
+                    .map_or(0.0, |r| weigh(r.background_steps()));
+                let expanded = scenario.expand_examples();
+                expanded.into_iter().map(move |s| (rule, s))
+            })
+            .map(|(rule, s)| background + rule + weigh(&s.steps))
+            .sum()
+    }
+}
+
+/// Convenience methods for [`Scenario`]
+pub trait ScenarioExt: sealed::Sealed {
+    /// Whether the scenario has examples to expand. This is independent of the keyword it was
+    /// written with, which [`Scenario::outline`] records: a `Scenario` may have examples, and a
+    /// `Scenario Outline` may have none.
+    fn has_examples(&self) -> bool;
+
+    /// The number following `prefix` in the first of the scenario's tags that consists of
+    /// `prefix` and an integer, such as `10` for the tag `order-10` and the prefix `order-`.
+    fn tag_number(&self, prefix: &str) -> Option<i64>;
+
+    /// The number of data rows in the scenario's examples, which is zero if it has none.
+    fn example_count(&self) -> usize;
+
+    /// The number of steps in a single run of the scenario, after the steps of `feature`'s
+    /// background, and of the background of the rule containing it, if `include_background`
+    /// is set. The steps of an outline are counted once; see [`FeatureExt::total_step_count`] to
+    /// count every run.
+    ///
+    /// The containing rule is found by identity, as in [`FeatureExt::effective_tags`].
+    fn step_count(&self, include_background: bool, feature: &Feature) -> usize;
+
+    /// The line that names the scenario in a rerun file: that of the examples row it was expanded
+    /// from, if any, or else that of the scenario itself. See [`rerun_entry`](crate::rerun_entry).
+    fn source_line(&self) -> usize;
+}
+
+impl ScenarioExt for Scenario {
+    fn has_examples(&self) -> bool {
+        !self.examples.is_empty()
+    }
+
+    fn tag_number(&self, prefix: &str) -> Option<i64> {
+        self.tags
+            .iter()
+            .filter_map(|t| t.strip_prefix(prefix)?.parse().ok())
+            .next()
+    }
+
+    fn example_count(&self) -> usize {
+        self.examples.iter().flat_map(|e| e.rows()).count()
+    }
+
+    fn step_count(&self, include_background: bool, feature: &Feature) -> usize {
+        let background = if include_background {
+            let rule = feature
+                .rule_of(self)
+                .map_or(&[][..], Rule::background_steps);
+            feature.background_steps().len() + rule.len()
+        } else {
+            0
+        };
+
+        background + self.steps.len()
+    }
+
+    fn source_line(&self) -> usize {
+        self.example_row.unwrap_or(self.position).0
+    }
+}
+
+/// Convenience methods for [`Step`]
+///
+/// These replace the deprecated inherent `Step::docstring` and `Step::table`, under names of
+/// their own so that calls to either are never ambiguous.
+pub trait StepExt: sealed::Sealed {
+    /// The docstring, if provided.
+    fn docstring_text(&self) -> Option<&str>;
+
+    /// The data table, if provided.
+    fn data_table(&self) -> Option<&Table>;
+
+    /// The step's value with each run of whitespace, including non-breaking and ideographic
+    /// spaces, replaced by a single ASCII space, and none at either end. For matching against
+    /// step definitions regardless of spacing that is invisible in review; the docstring and
+    /// table are not included.
+    fn normalized_value(&self) -> String;
+}
+
+impl StepExt for Step {
+    fn docstring_text(&self) -> Option<&str> {
+        self.docstring.as_deref()
+    }
+
+    fn data_table(&self) -> Option<&Table> {
+        self.table.as_ref()
+    }
+
+    fn normalized_value(&self) -> String {
+        self.value.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Convenience methods for [`Table`]
+pub trait TableExt: sealed::Sealed {
+    /// The first row of the table, which names the columns of an examples table.
+    fn header(&self) -> Option<&[String]>;
+
+    /// The column the opening `|` of every row is at, or `None` if the rows are indented
+    /// differently or their positions were not recorded.
+    fn indentation(&self) -> Option<usize>;
+
+    /// Whether the table has no rows, not even a header.
+    fn is_empty(&self) -> bool;
+
+    /// The number of rows, including the header row, and the number of columns.
+    fn dimensions(&self) -> (usize, usize);
+}
+
+impl TableExt for Table {
+    fn header(&self) -> Option<&[String]> {
+        self.rows.first().map(|r| &**r)
+    }
+
+    fn indentation(&self) -> Option<usize> {
+        let (_, column) = *self.row_positions.first()?;
+        Some(column).filter(|_| self.row_positions.iter().all(|p| p.1 == column))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    fn dimensions(&self) -> (usize, usize) {
+        (self.rows.len(), self.row_width())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn extension_methods_from_the_prelude() {
+        let feature = Feature::parse(
+            "Feature: A\n  Scenario Outline: B\n    Given <x>\n      \"\"\"\n      text\n      \"\"\"\n\n    Examples:\n      | x |\n      | 1 |\n",
+        )
+        .unwrap();
+        let outline = &feature.scenarios[0];
+
+        assert_eq!(feature.scenario_count(), 1);
+        assert!(outline.has_examples());
+        assert_eq!(
+            outline.steps[0].docstring_text().map(str::trim),
+            Some("text")
+        );
+        assert_eq!(outline.steps[0].data_table(), None);
+        let table = outline.examples[0].table.as_ref().unwrap();
+        assert_eq!(table.header(), Some(&["x".to_string()][..]));
+    }
+
+    #[test]
+    fn pickle_names_expand_outlines() {
+        let feature = Feature::parse(
+            r"Feature: Dry run
+  Background:
+    Given a browser
+
+  Scenario Outline: Opening <page>
+    When I open <page>
+
+    Examples:
+      | page  |
+      | home  |
+      | about |
+      | help  |
+
+  Scenario: Closing
+    When I close it
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            feature.as_pickle_names(),
+            vec!["Opening home", "Opening about", "Opening help", "Closing"]
+        );
+    }
+}
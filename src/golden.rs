@@ -0,0 +1,301 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt::Write;
+
+use crate::{Background, Examples, Feature, Rule, RuleChild, Scenario, Step, Table};
+
+const INDENT: usize = 2;
+
+impl Feature {
+    /// A canonical, line-based rendering of the feature's contents for snapshot tests.
+    ///
+    /// Each node is written on its own line, indented under its parent, with text quoted and
+    /// escaped. Spans, positions, blank lines, the final newline and the path are left out, so
+    /// reformatting a file does not change its digest but any change to what was parsed does.
+    pub fn golden_digest(&self) -> String {
+        let mut digest = Digest(String::new());
+        digest.feature(self);
+        digest.0
+    }
+}
+
+struct Digest(String);
+
+impl Digest {
+    fn line(&mut self, depth: usize, args: std::fmt::Arguments<'_>) {
+        self.0.extend(std::iter::repeat_n(' ', depth * INDENT));
+        self.0.write_fmt(args).unwrap();
+        self.0.push('\n');
+    }
+
+    fn tags(&mut self, depth: usize, tags: &[String]) {
+        if !tags.is_empty() {
+            self.line(depth, format_args!("tags {:?}", tags));
+        }
+    }
+
+    fn description(&mut self, depth: usize, description: &Option<String>) {
+        if let Some(description) = description {
+            self.line(depth, format_args!("description {:?}", description));
+        }
+    }
+
+    fn feature(&mut self, feature: &Feature) {
+        let Feature {
+            name,
+            description,
+            background,
+            scenarios,
+            rules,
+            tags,
+            tag_tokens: _,
+            span: _,
+            position: _,
+            path: _,
+            trailing_newline: _,
+            line_ending: _,
+        } = feature;
+
+        self.line(0, format_args!("feature {:?}", name));
+        self.tags(1, tags);
+        self.description(1, description);
+
+        if let Some(background) = background {
+            self.background(1, background);
+        }
+
+        for scenario in scenarios {
+            self.scenario(1, scenario);
+        }
+
+        for rule in rules {
+            self.rule(1, rule);
+        }
+    }
+
+    fn background(&mut self, depth: usize, background: &Background) {
+        let Background {
+            steps,
+            description,
+            span: _,
+            position: _,
+            leading_blank_lines: _,
+        } = background;
+
+        self.line(depth, format_args!("background"));
+        self.description(depth + 1, description);
+        self.steps(depth + 1, steps);
+    }
+
+    fn rule(&mut self, depth: usize, rule: &Rule) {
+        let Rule {
+            name,
+            description,
+            children,
+            tags,
+            tag_tokens: _,
+            span: _,
+            position: _,
+            leading_blank_lines: _,
+        } = rule;
+
+        self.line(depth, format_args!("rule {:?}", name));
+        self.tags(depth + 1, tags);
+        self.description(depth + 1, description);
+
+        for child in children {
+            match child {
+                RuleChild::Background(background) => self.background(depth + 1, background),
+                RuleChild::Scenario(scenario) => self.scenario(depth + 1, scenario),
+            }
+        }
+    }
+
+    fn scenario(&mut self, depth: usize, scenario: &Scenario) {
+        let Scenario {
+            name,
+            steps,
+            examples,
+            tags,
+            tag_tokens: _,
+            span: _,
+            position: _,
+            leading_blank_lines: _,
+            example_row: _,
+            example_index: _,
+            outline: _,
+        } = scenario;
+
+        self.line(depth, format_args!("scenario {:?}", name));
+        self.tags(depth + 1, tags);
+        self.steps(depth + 1, steps);
+
+        for examples in examples {
+            self.examples(depth + 1, examples);
+        }
+    }
+
+    fn examples(&mut self, depth: usize, examples: &Examples) {
+        let Examples {
+            table,
+            tags,
+            tag_tokens: _,
+            span: _,
+            position: _,
+            leading_blank_lines: _,
+        } = examples;
+
+        self.line(depth, format_args!("examples"));
+        self.tags(depth + 1, tags);
+
+        if let Some(table) = table {
+            self.table(depth + 1, table);
+        }
+    }
+
+    fn steps(&mut self, depth: usize, steps: &[Step]) {
+        for step in steps {
+            let Step {
+                ty,
+                raw_type,
+                value,
+                docstring,
+                docstring_delimiter,
+                docstring_span: _,
+                table,
+                sub_steps,
+                span: _,
+                position: _,
+                leading_blank_lines: _,
+                origin,
+                from_background,
+            } = step;
+
+            self.line(
+                depth,
+                format_args!("step {:?} {:?} {:?}", ty, raw_type, value),
+            );
+
+            if let Some(origin) = origin {
+                self.line(depth + 1, format_args!("origin {:?}", origin));
+            }
+
+            if *from_background {
+                self.line(depth + 1, format_args!("from background"));
+            }
+
+            if let Some(docstring) = docstring {
+                self.line(
+                    depth + 1,
+                    format_args!("docstring {} {:?}", docstring_delimiter.as_str(), docstring),
+                );
+            }
+
+            if let Some(table) = table {
+                self.table(depth + 1, table);
+            }
+
+            self.steps(depth + 1, sub_steps);
+        }
+    }
+
+    fn table(&mut self, depth: usize, table: &Table) {
+        let Table {
+            rows,
+            row_positions: _,
+            row_comments,
+            row_leading_comments,
+            span: _,
+            position: _,
+        } = table;
+
+        self.line(depth, format_args!("table"));
+        for (i, row) in rows.iter().enumerate() {
+            for comment in row_leading_comments.get(i).into_iter().flatten() {
+                self.line(depth + 1, format_args!("comment {:?}", comment));
+            }
+
+            match row_comments.get(i).and_then(Option::as_ref) {
+                Some(comment) => {
+                    self.line(depth + 1, format_args!("row {:?} # {:?}", row, comment))
+                }
+                None => self.line(depth + 1, format_args!("row {:?}", row)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks each `tests/fixtures/*.feature` against the digest in the `.digest` file beside
+    /// it. Run with `UPDATE_GOLDEN=1` to write the digests afresh after an intended change,
+    /// then review the diff.
+    #[test]
+    fn fixtures_match_digests() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        let mut paths: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == "feature"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        for path in paths {
+            let digest = Feature::parse_path(&path)
+                .unwrap_or_else(|e| panic!("{}", e))
+                .golden_digest();
+            let golden = path.with_extension("digest");
+
+            if update {
+                std::fs::write(&golden, &digest).unwrap();
+            } else {
+                let expected = std::fs::read_to_string(&golden)
+                    .unwrap_or_else(|_| panic!("missing {}", golden.display()));
+                assert_eq!(digest, expected, "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn digest_ignores_layout() {
+        let compact = "@web\nFeature: Login\nScenario: Valid\nGiven a user\n| name |\n| bob |\nWhen they log in\n";
+        let spaced = r"@web
+Feature: Login
+
+  # A comment
+  Scenario: Valid
+    Given a user
+      | name |
+      | bob  |
+
+    When they log in
+";
+        let compact = Feature::parse(compact).unwrap();
+        let spaced = Feature::parse(spaced).unwrap();
+
+        assert_ne!(compact, spaced);
+        assert_eq!(compact.golden_digest(), spaced.golden_digest());
+        assert_eq!(
+            spaced.golden_digest(),
+            r#"feature "Login"
+  tags ["web"]
+  scenario "Valid"
+    step Given "Given" "a user"
+      table
+        row ["name"]
+        row ["bob"]
+    step When "When" "they log in"
+"#
+        );
+    }
+}
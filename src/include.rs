@@ -0,0 +1,222 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io;
+use std::sync::Arc;
+
+use crate::parser::{self, gherkin_parser, GherkinEnv};
+use crate::{Background, Diagnostic, Feature, ParseOptions, Severity, Step};
+
+/// Looks up the text of an included file, as set with
+/// [`ParseOptions::with_include_resolver`].
+#[derive(Clone)]
+pub(crate) struct IncludeResolver(pub(crate) Arc<Resolve>);
+
+type Resolve = dyn Fn(&str) -> io::Result<String> + Send + Sync;
+
+impl std::fmt::Debug for IncludeResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("IncludeResolver")
+    }
+}
+
+impl PartialEq for IncludeResolver {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for IncludeResolver {}
+
+type IncludeError = (&'static str, String);
+
+/// Places the background steps named by the include comments at the top of `source` before
+/// the feature's own, recording any include that could not be resolved in `diagnostics`.
+pub(crate) fn splice(
+    feature: &mut Feature,
+    source: &str,
+    options: &ParseOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = match options.include_resolver() {
+        Some(resolver) => resolver,
+        None => return,
+    };
+
+    let mut steps = vec![];
+    let mut errors = vec![];
+
+    for (span, path) in includes(source) {
+        match resolve(path, resolver, options, &mut vec![]) {
+            Ok(included) => steps.extend(included),
+            Err((code, message)) => errors.push(Diagnostic {
+                severity: Severity::Error,
+                code,
+                message,
+                span,
+                position: position(source, span.0),
+                fix: None,
+            }),
+        }
+    }
+
+    // Include comments come before anything the parser could have reported on.
+    diagnostics.splice(0..0, errors);
+
+    if !steps.is_empty() {
+        let background = feature
+            .background
+            .get_or_insert_with(|| Background::builder().steps(vec![]).build());
+        steps.append(&mut background.steps);
+        background.steps = steps;
+    }
+}
+
+/// The steps of the background included from `path`, preceded by those it includes in turn.
+/// `stack` holds the includes currently being resolved, to detect cycles.
+fn resolve(
+    path: &str,
+    resolver: &IncludeResolver,
+    options: &ParseOptions,
+    stack: &mut Vec<String>,
+) -> Result<Vec<Step>, IncludeError> {
+    if stack.iter().any(|p| p == path) {
+        let chain = stack.iter().map(|p| &**p).chain(std::iter::once(path));
+        let chain = chain.collect::<Vec<_>>().join(" -> ");
+        return Err(("include-cycle", format!("include cycle: {}", chain)));
+    }
+
+    let text = (resolver.0)(path).map_err(|e| {
+        (
+            "include-failed",
+            format!("could not include {}: {}", path, e),
+        )
+    })?;
+
+    stack.push(path.to_string());
+    let mut steps = vec![];
+    for (_, nested) in includes(&text) {
+        steps.extend(resolve(nested, resolver, options, stack)?);
+    }
+    stack.pop();
+
+    let input = parser::prepare(&text, options);
+    let env = GherkinEnv::with_options(&input, options.clone());
+    let background = gherkin_parser::background_snippet(&input, &env)
+        .map_err(|e| ("include-failed", format!("could not parse {}: {}", path, e)))?;
+
+    steps.extend(background.steps.into_iter().map(|mut step| {
+        step.origin = Some(path.to_string());
+        step
+    }));
+
+    Ok(steps)
+}
+
+/// The span and path of each `# include:` comment before the first line of content.
+fn includes(source: &str) -> Vec<((usize, usize), &str)> {
+    let mut offset = 0;
+    let mut out = vec![];
+
+    for line in source.split('\n') {
+        let trimmed = line.trim();
+        if let Some(comment) = trimmed.strip_prefix('#') {
+            if let Some(path) = include_path(comment) {
+                let start = offset + line.len() - line.trim_start().len();
+                out.push(((start, start + trimmed.len()), path));
+            }
+        } else if !trimmed.is_empty() {
+            break;
+        }
+
+        offset += line.len() + 1;
+    }
+
+    out
+}
+
+/// The path of an `# include:` comment, given the text after the `#`.
+fn include_path(comment: &str) -> Option<&str> {
+    let path = comment.trim_start().strip_prefix("include")?;
+    let path = path.trim_start().strip_prefix(':')?.trim();
+    Some(path).filter(|p| !p.is_empty())
+}
+
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    (
+        source[..offset].matches('\n').count() + 1,
+        offset - start + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolver(path: &str) -> io::Result<String> {
+        match path {
+            "common/login.background" => Ok(
+                "# include: common/site.background\nBackground:\n  Given I am logged in\n"
+                    .to_string(),
+            ),
+            "common/site.background" => Ok("Background:\n  Given the site is up\n".to_string()),
+            "loop/a.background" => Ok("# include: loop/b.background\nBackground:\n".to_string()),
+            "loop/b.background" => Ok("# include: loop/a.background\nBackground:\n".to_string()),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
+        }
+    }
+
+    fn parse(source: &str) -> crate::ParsedFeature {
+        let options = ParseOptions::default().with_include_resolver(resolver);
+        Feature::parse_with_options(source.to_string(), &options).unwrap()
+    }
+
+    #[test]
+    fn splices_included_backgrounds() {
+        let source = "# include: common/login.background\nFeature: Account\n  Background:\n    Given an account\n\n  Scenario: Viewing\n    When I view it\n";
+        let parsed = parse(source);
+        let steps = &parsed.feature.background.as_ref().unwrap().steps;
+
+        let values: Vec<_> = steps.iter().map(|s| &*s.value).collect();
+        assert_eq!(
+            values,
+            vec!["the site is up", "I am logged in", "an account"]
+        );
+        let origins: Vec<_> = steps.iter().map(|s| s.origin.as_deref()).collect();
+        assert_eq!(
+            origins,
+            vec![
+                Some("common/site.background"),
+                Some("common/login.background"),
+                None
+            ]
+        );
+        assert!(parsed.diagnostics.is_empty());
+
+        let plain = Feature::parse(source).unwrap();
+        assert_eq!(plain.background.unwrap().steps.len(), 1);
+    }
+
+    #[test]
+    fn reports_cycles_and_failures() {
+        let source =
+            "# include: loop/a.background\n# include: missing.background\nFeature: Loops\n";
+        let parsed = parse(source);
+
+        assert_eq!(parsed.feature.background, None);
+        assert_eq!(parsed.diagnostics.len(), 2);
+        assert_eq!(parsed.diagnostics[0].code, "include-cycle");
+        assert_eq!(
+            parsed.diagnostics[0].message,
+            "include cycle: loop/a.background -> loop/b.background -> loop/a.background"
+        );
+        assert_eq!(parsed.diagnostics[1].code, "include-failed");
+        assert_eq!(parsed.diagnostics[1].position, (2, 1));
+    }
+}
@@ -9,7 +9,24 @@
 //! A Gherkin parser for the Cucumber test framework.
 //!
 //! It is intended to parse the full gamut of Cucumber .feature files that exist in the wild,
-//! as there is only a _de facto_ standard for these files.
+//! as there is only a _de facto_ standard for these files. Parsing with [`ParseMode::Strict`]
+//! instead follows the reference implementation's decisions on what to accept.
+//!
+//! ### Parsing
+//!
+//! ```
+//! use gherkin_rust::Feature;
+//!
+//! let feature = Feature::parse("Feature: Eating\n  Scenario: Lunch\n    Given a sandwich\n")
+//!     .expect("a valid feature");
+//! assert_eq!(feature.scenarios[0].steps[0].value, "a sandwich");
+//!
+//! let err = Feature::parse("Scenario: No feature\n").unwrap_err();
+//! assert_eq!(err.location.line, 1);
+//! ```
+//!
+//! [`Feature::parse_path`] reads and parses a file, and [`Feature::parse_with_options`]
+//! controls how strictly the input is read.
 //!
 //! ### .feature file structure
 //!
@@ -31,48 +48,177 @@
 //!
 //! Indentation and comments are ignored by the parser. Most other things can be accessed via
 //! properties of the relevant struct.
+//!
+//! ### Deterministic output
+//!
+//! Collections returned by this crate preserve document order (`Vec`) or are sorted
+//! (`BTreeMap`, `BTreeSet`), so their iteration order is the same across runs and platforms.
+//! The derived `Hash` impls make no such promise; use [`Feature::stable_hash`] where a
+//! persistent value is needed.
+//!
+//! ### Equality and hashing
+//!
+//! The derived `PartialEq`, `Eq` and `Hash` impls take every field into account, including
+//! spans, positions, blank line counts and the path, and fields added in later versions will
+//! take part too. Two parses of the same file compare equal, but reformatting it, even only
+//! re-indenting a line, makes them differ. To compare or key features by what they say rather
+//! than how they are laid out, use [`Feature::golden_digest`].
+//!
+//! ### Thread safety
+//!
+//! The parsed types, such as [`Feature`] and [`ParsedFeature`], along with the options and
+//! error types, own all their data and are `Send + Sync + 'static`, so parsed features can be
+//! shared between threads in an `Arc`. This is checked when the crate is compiled, and will
+//! be kept.
+//!
+//! The views borrowing from a feature, such as [`Row`], [`FeatureChild`] and [`TagUsage`],
+//! are `Send + Sync` for as long as the feature they borrow from.
 
+#[cfg(feature = "compat")]
+pub mod compat;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod coverage;
+mod dialect;
+mod ext;
+mod golden;
+mod hash;
+mod include;
+mod links;
+mod lint;
+mod options;
 mod parser;
+pub mod prelude;
+mod report;
+mod reporter;
+mod row;
+mod stepref;
 pub mod tagexpr;
+mod token;
+mod writer;
 
 // Re-export for convenience
 pub use peg::error::ParseError;
 pub use peg::str::LineCol;
 
+pub use dialect::{Dialect, StepKeywordError};
+pub use ext::{FeatureExt, ScenarioExt, StepExt, TableExt};
+pub use links::{resolve_links, DanglingLink, Link, LinkGraph, LinkNode};
+pub use lint::{Diagnostic, Severity};
+pub use options::{ExpandOptions, ParseMetrics, ParseMode, ParseOptions};
+pub use report::{tag_report, TagExplanation, TagMatch, TagOccurrence, TagOwner, TagUsage};
+pub use reporter::{ErrorReporter, RustcReporter};
+pub use row::{CellParseError, ExampleRow, Row};
+pub use stepref::{InvalidStepRef, StepRefId};
+pub use token::{token_scan, LineToken, TokenKind};
+pub use writer::FormatOptions;
+
 use typed_builder::TypedBuilder;
 
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 
+use tagexpr::TagOperation;
+
+// Fails to compile if a public type stops being safe to share between threads. See "Thread
+// safety" in the crate docs.
+const _: () = {
+    fn owned<T: Send + Sync + 'static>() {}
+    fn borrowed<T: Send + Sync>() {}
+
+    #[allow(dead_code)]
+    fn assert_thread_safe() {
+        owned::<Feature>();
+        owned::<Background>();
+        owned::<Rule>();
+        owned::<RuleChild>();
+        owned::<Scenario>();
+        owned::<Step>();
+        owned::<Examples>();
+        owned::<Table>();
+        owned::<Tag>();
+        owned::<TextEdit>();
+        owned::<ParsedFeature>();
+        owned::<Diagnostic>();
+        owned::<ParseMetrics>();
+        owned::<ParseOptions>();
+        owned::<ExpandOptions>();
+        owned::<FormatOptions>();
+        owned::<Dialect>();
+        owned::<TagOperation>();
+        owned::<StepRefId>();
+        owned::<LineToken>();
+        owned::<ParseError<LineCol>>();
+        owned::<ParseFileError>();
+        owned::<ParseSourceError>();
+        owned::<NamedParseError>();
+        owned::<TooManyExpansions>();
+        owned::<TableTooWide>();
+        owned::<BackgroundConflict>();
+        owned::<StepKeywordError>();
+        owned::<CellParseError>();
+        owned::<InvalidStepRef>();
+        owned::<Link>();
+        #[cfg(feature = "compat")]
+        owned::<compat::Pickle>();
+        #[cfg(feature = "compat")]
+        owned::<compat::ExcerptOptions>();
+
+        borrowed::<Row<'_>>();
+        borrowed::<FeatureChild<'_>>();
+        borrowed::<ScenarioParent<'_>>();
+        borrowed::<TagUsage<'_>>();
+        borrowed::<TagExplanation<'_>>();
+        borrowed::<LinkGraph<'_>>();
+    }
+};
+
 /// A feature background
 #[derive(Debug, Clone, TypedBuilder, PartialEq, Hash, Eq)]
 pub struct Background {
     /// The parsed steps from the background directive.
     pub steps: Vec<Step>,
+    /// The text between the background directive and its first step, if any.
+    #[builder(default)]
+    pub description: Option<String>,
     /// The `(start, end)` offset the background directive was found in the .feature file.
     #[builder(default)]
     pub span: (usize, usize),
     /// The `(line, col)` position the background directive was found in the .feature file.
     #[builder(default)]
     pub position: (usize, usize),
+    /// The number of blank lines before the background (or its tags) in the .feature file.
+    #[builder(default)]
+    pub leading_blank_lines: usize,
 }
 
 /// Examples for a scenario
 #[derive(Debug, Clone, TypedBuilder, PartialEq, Hash, Eq)]
 pub struct Examples {
-    /// The data table from the examples directive.
-    pub table: Table,
+    /// The data table from the examples directive, or `None` if the keyword is not followed by
+    /// a table. A table with only a header row has no data rows.
+    #[builder(default)]
+    pub table: Option<Table>,
     /// The tags for the examples directive if provided.
     #[builder(default)]
     pub tags: Vec<String>,
+    /// The tags with their locations in the .feature file, parallel to `tags`.
+    #[builder(default)]
+    pub tag_tokens: Vec<Tag>,
     /// The `(start, end)` offset the examples directive was found in the .feature file.
     #[builder(default)]
     pub span: (usize, usize),
     /// The `(line, col)` position the examples directive was found in the .feature file.
     #[builder(default)]
     pub position: (usize, usize),
+    /// The number of blank lines before the examples (or its tags) in the .feature file.
+    #[builder(default)]
+    pub leading_blank_lines: usize,
 }
 
 /// A feature
+///
+/// The derived `Hash` is only suitable for in-memory collections: its value is not guaranteed
+/// to be the same across platforms or crate versions. See [`Feature::stable_hash`].
 #[derive(Debug, Clone, TypedBuilder, PartialEq, Hash, Eq)]
 pub struct Feature {
     /// The name of the feature.
@@ -92,6 +238,9 @@ pub struct Feature {
     /// The tags for the feature if provided.
     #[builder(default)]
     pub tags: Vec<String>,
+    /// The tags with their locations in the .feature file, parallel to `tags`.
+    #[builder(default)]
+    pub tag_tokens: Vec<Tag>,
     /// The `(start, end)` offset the feature directive was found in the .feature file.
     #[builder(default)]
     pub span: (usize, usize),
@@ -101,6 +250,12 @@ pub struct Feature {
     /// The path supplied for the parsed `Feature`, if known.
     #[builder(default)]
     pub path: Option<PathBuf>,
+    /// Whether the .feature file ends with a newline.
+    #[builder(default = true)]
+    pub trailing_newline: bool,
+    /// The line ending used most in the .feature file, which it is rendered with.
+    #[builder(default)]
+    pub line_ending: LineEnding,
 }
 
 impl PartialOrd for Feature {
@@ -118,19 +273,102 @@ impl Ord for Feature {
 /// A rule, as introduced in Gherkin 6.
 #[derive(Debug, Clone, TypedBuilder, PartialEq, Hash, Eq)]
 pub struct Rule {
-    /// The name of the scenario.
+    /// The name of the rule.
     pub name: String,
-    /// The parsed scenarios from the rule directive.
-    pub scenarios: Vec<Scenario>,
+    /// The description of the rule, if found.
+    #[builder(default)]
+    pub description: Option<String>,
+    /// The background and scenarios of the rule, in the order they appear in the .feature file.
+    ///
+    /// A rule has at most one background. It comes before the scenarios unless parsed
+    /// permissively, which accepts it after some of them; see [`ParseMode`].
+    pub children: Vec<RuleChild>,
     /// The tags for the rule directive if provided.
     #[builder(default)]
     pub tags: Vec<String>,
+    /// The tags with their locations in the .feature file, parallel to `tags`.
+    #[builder(default)]
+    pub tag_tokens: Vec<Tag>,
     /// The `(start, end)` offset the rule directive was found in the .feature file.
     #[builder(default)]
     pub span: (usize, usize),
     /// The `(line, col)` position the rule directive was found in the .feature file.
     #[builder(default)]
     pub position: (usize, usize),
+    /// The number of blank lines before the rule (or its tags) in the .feature file.
+    #[builder(default)]
+    pub leading_blank_lines: usize,
+}
+
+/// A background or scenario directly inside a rule
+#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+pub enum RuleChild {
+    Background(Background),
+    Scenario(Scenario),
+}
+
+impl Rule {
+    /// The background of the rule, if it has one.
+    pub fn background(&self) -> Option<&Background> {
+        self.children.iter().find_map(|c| match c {
+            RuleChild::Background(b) => Some(b),
+            RuleChild::Scenario(_) => None,
+        })
+    }
+
+    /// The background of the rule, if it has one.
+    pub fn background_mut(&mut self) -> Option<&mut Background> {
+        self.children.iter_mut().find_map(|c| match c {
+            RuleChild::Background(b) => Some(b),
+            RuleChild::Scenario(_) => None,
+        })
+    }
+
+    /// The scenarios of the rule, in document order.
+    pub fn scenarios(&self) -> impl Iterator<Item = &Scenario> {
+        self.children.iter().filter_map(|c| match c {
+            RuleChild::Background(_) => None,
+            RuleChild::Scenario(s) => Some(s),
+        })
+    }
+
+    /// The scenarios of the rule, in document order.
+    pub fn scenarios_mut(&mut self) -> impl Iterator<Item = &mut Scenario> {
+        self.children.iter_mut().filter_map(|c| match c {
+            RuleChild::Background(_) => None,
+            RuleChild::Scenario(s) => Some(s),
+        })
+    }
+
+    /// The steps of the rule's background, or none if it has no background.
+    pub fn background_steps(&self) -> &[Step] {
+        self.background().map_or(&[], |b| &b.steps)
+    }
+
+    /// The steps of the rule's background and scenarios, in document order.
+    fn steps(&self) -> impl Iterator<Item = &Step> {
+        self.children.iter().flat_map(|c| match c {
+            RuleChild::Background(b) => b.steps.iter(),
+            RuleChild::Scenario(s) => s.steps.iter(),
+        })
+    }
+
+    fn steps_mut(&mut self) -> impl Iterator<Item = &mut Step> {
+        self.children.iter_mut().flat_map(|c| match c {
+            RuleChild::Background(b) => b.steps.iter_mut(),
+            RuleChild::Scenario(s) => s.steps.iter_mut(),
+        })
+    }
+
+    /// Sorts the scenarios of the rule by `key`, leaving the background where it is. Scenarios
+    /// with equal keys keep their order.
+    pub(crate) fn sort_scenarios_by_key<K: Ord>(&mut self, key: impl Fn(&Scenario) -> K) {
+        let mut scenarios: Vec<Scenario> = self.scenarios().cloned().collect();
+        scenarios.sort_by_key(|s| key(s));
+        for (slot, scenario) in self.scenarios_mut().zip(scenarios) {
+            *slot = scenario;
+        }
+    }
 }
 
 /// A scenario
@@ -140,18 +378,38 @@ pub struct Scenario {
     pub name: String,
     /// The parsed steps from the scenario directive.
     pub steps: Vec<Step>,
-    // The parsed examples from the scenario directive if found.
+    /// The parsed examples blocks from the scenario directive, in document order.
     #[builder(default)]
-    pub examples: Option<Examples>,
+    pub examples: Vec<Examples>,
     /// The tags for the scenarios directive if provided.
     #[builder(default)]
     pub tags: Vec<String>,
+    /// The tags with their locations in the .feature file, parallel to `tags`.
+    #[builder(default)]
+    pub tag_tokens: Vec<Tag>,
     /// The `(start, end)` offset the scenario directive was found in the .feature file.
     #[builder(default)]
     pub span: (usize, usize),
     /// The `(line, col)` position the scenario directive was found in the .feature file.
     #[builder(default)]
     pub position: (usize, usize),
+    /// The number of blank lines before the scenario (or its tags) in the .feature file.
+    #[builder(default)]
+    pub leading_blank_lines: usize,
+    /// The `(line, col)` position of the examples row the scenario was expanded from by
+    /// [`Scenario::expand_examples`], or `None` if it was not.
+    #[builder(default)]
+    pub example_row: Option<(usize, usize)>,
+    /// The index of the examples block and of the data row within it (not counting the header
+    /// row) that the scenario was expanded from by [`Scenario::expand_examples`], or `None` if
+    /// it was not.
+    #[builder(default)]
+    pub example_index: Option<(usize, usize)>,
+    /// Whether the scenario was written with the `Scenario Outline` keyword rather than
+    /// `Scenario`. The scenarios an outline expands to are not outlines. Whether it has
+    /// examples to expand is given by [`ScenarioExt::has_examples`].
+    #[builder(default)]
+    pub outline: bool,
 }
 
 /// A scenario step
@@ -161,20 +419,156 @@ pub struct Step {
     pub ty: StepType,
     /// The original raw step type, including `But` and `And`.
     pub raw_type: String,
-    /// The value of the step after the type.
+    /// The value of the step after the type, exactly as written. Unlike table cells, no escape
+    /// sequences are interpreted, so `C:\new` remains a backslash followed by `n`.
     pub value: String,
     /// A docstring, if provided.
+    ///
+    /// Each line has the indentation of the opening delimiter removed, measuring a tab as
+    /// advancing to the next multiple of eight columns, so deeper indentation is kept relative to
+    /// it. The delimiter escaped with backslashes, as in `\"\"\"`, is read as the delimiter.
     #[builder(default)]
     pub docstring: Option<String>,
+    /// The delimiter the docstring was written between, which is kept when it is rendered.
+    #[builder(default)]
+    pub docstring_delimiter: DocstringDelimiter,
+    /// The `(start, end)` offset of the docstring in the .feature file, from its opening
+    /// delimiter to its closing one, or to the end of its last line of text if it was never
+    /// closed.
+    #[builder(default)]
+    pub docstring_span: Option<(usize, usize)>,
     /// A data table, if provided.
     #[builder(default)]
     pub table: Option<Table>,
+    /// The `*` bullets indented under the step, when read as its sub-steps. See
+    /// [`ParseOptions::with_sub_steps`].
+    #[builder(default)]
+    pub sub_steps: Vec<Step>,
     /// The `(start, end)` offset the step directive was found in the .feature file.
     #[builder(default)]
     pub span: (usize, usize),
     /// The `(line, col)` position the step directive was found in the .feature file.
     #[builder(default)]
     pub position: (usize, usize),
+    /// The number of blank lines before the step (or its tags) in the .feature file.
+    #[builder(default)]
+    pub leading_blank_lines: usize,
+    /// The path of the `# include:` comment the step was spliced in from, or `None` if it was
+    /// written in this file. The span and position of an included step are in the included file.
+    /// See [`ParseOptions::with_include_resolver`].
+    #[builder(default)]
+    pub origin: Option<String>,
+    /// Whether the step was moved into its scenario from the feature's background by
+    /// [`Feature::inline_background`].
+    #[builder(default)]
+    pub from_background: bool,
+}
+
+/// A tag, as found in the .feature file
+#[derive(Debug, Clone, TypedBuilder, PartialEq, Hash, Eq)]
+pub struct Tag {
+    /// The name of the tag, without the leading `@`.
+    pub name: String,
+    /// The `(start, end)` offset of the tag, including the `@`, in the .feature file.
+    #[builder(default)]
+    pub span: (usize, usize),
+    /// The `(line, col)` position the tag was found in the .feature file.
+    #[builder(default)]
+    pub position: (usize, usize),
+}
+
+/// A replacement of the text covered by `span` in a .feature file
+#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+pub struct TextEdit {
+    /// The `(start, end)` offset of the text to replace.
+    pub span: (usize, usize),
+    /// The text to insert in place of the span.
+    pub replacement: String,
+}
+
+impl TextEdit {
+    /// Applies the edit to the source it was computed from.
+    pub fn apply(&self, source: &str) -> String {
+        let (start, end) = self.span;
+        format!("{}{}{}", &source[..start], self.replacement, &source[end..])
+    }
+}
+
+/// A style of line ending
+///
+/// Parsing accepts any mix of them, reading `\r\n`, `\n` and a lone `\r` each as the end of a
+/// line. None of them is kept in the parsed text, where lines are always separated by `\n`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Hash, Eq)]
+pub enum LineEnding {
+    /// `\n`
+    #[default]
+    Lf,
+    /// `\r\n`
+    CrLf,
+    /// `\r`
+    Cr,
+}
+
+impl LineEnding {
+    /// The line ending as written.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// The line ending used most in `source`, preferring `\n` in a tie.
+    fn predominant(source: &str) -> LineEnding {
+        let crlf = source.matches("\r\n").count();
+        let lf = source.matches('\n').count() - crlf;
+        let cr = source.matches('\r').count() - crlf;
+
+        if crlf > lf && crlf >= cr {
+            LineEnding::CrLf
+        } else if cr > lf && cr > crlf {
+            LineEnding::Cr
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+/// The delimiter written around a docstring
+#[derive(Debug, Clone, Copy, Default, PartialEq, Hash, Eq)]
+pub enum DocstringDelimiter {
+    /// `"""`
+    #[default]
+    Quotes,
+    /// ```` ``` ````
+    Backticks,
+}
+
+impl DocstringDelimiter {
+    /// The delimiter as written.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocstringDelimiter::Quotes => "\"\"\"",
+            DocstringDelimiter::Backticks => "```",
+        }
+    }
+
+    /// The delimiter as written inside a docstring it delimits, with each character escaped.
+    pub(crate) fn escaped(&self) -> &'static str {
+        match self {
+            DocstringDelimiter::Quotes => "\\\"\\\"\\\"",
+            DocstringDelimiter::Backticks => "\\`\\`\\`",
+        }
+    }
+
+    /// The other delimiter.
+    pub(crate) fn other(&self) -> DocstringDelimiter {
+        match self {
+            DocstringDelimiter::Quotes => DocstringDelimiter::Backticks,
+            DocstringDelimiter::Backticks => DocstringDelimiter::Quotes,
+        }
+    }
 }
 
 /// The fundamental Gherkin step type after contextually handling `But` and `And`
@@ -186,10 +580,32 @@ pub enum StepType {
 }
 
 /// A data table
+///
+/// Each row is written between pipes, as in `| a | b |`: the closing `|` ends the last cell
+/// rather than starting an empty one, so `| a | b ||` has three cells. A line missing the pipe
+/// at either end, such as `a | b |` or `| a | b`, is not a valid row; see [`ParseMode`].
+///
+/// Within a cell, `\|`, `\\` and `\n` stand for a pipe, a backslash and a newline, and are
+/// written back the same way, so any cell value survives being written and parsed again apart
+/// from whitespace at either end, which is not part of the value.
+///
+/// A row may be followed by a comment after its closing pipe, as in `| a | b | # flaky`, and
+/// comment lines between rows do not end the table. Both are kept with the row they belong to,
+/// without changing the numbering of the rows.
 #[derive(Debug, Clone, TypedBuilder, PartialEq, Hash, Eq)]
 pub struct Table {
     /// The rows of the data table. Each row is always the same length as the first row.
     pub rows: Vec<Vec<String>>,
+    /// The `(line, col)` position of the opening `|` of each row in the .feature file,
+    /// parallel to `rows`.
+    #[builder(default)]
+    pub row_positions: Vec<(usize, usize)>,
+    /// The comment after the closing `|` of each row, without its `#`, parallel to `rows`.
+    #[builder(default)]
+    pub row_comments: Vec<Option<String>>,
+    /// The comment lines directly above each row, without their `#`, parallel to `rows`.
+    #[builder(default)]
+    pub row_leading_comments: Vec<Vec<String>>,
     /// The `(start, end)` offset the table directive was found in the .feature file.
     #[builder(default)]
     pub span: (usize, usize),
@@ -200,11 +616,341 @@ pub struct Table {
 
 impl Table {
     pub fn row_width(&self) -> usize {
-        self.rows
+        self.rows.first().map(|x| x.len()).unwrap_or(0)
+    }
+
+    /// Checks that the table has no more than `max_columns` columns, as
+    /// [`ParseOptions::with_max_table_columns`] asks of the tables parsed.
+    pub fn check_width(&self, max_columns: usize) -> Result<(), TableTooWide> {
+        let columns = self.row_width();
+        if columns <= max_columns {
+            return Ok(());
+        }
+
+        Err(TableTooWide {
+            position: self.position,
+            columns,
+            max_columns,
+        })
+    }
+
+    /// The rows after the header row, addressable by column name.
+    pub fn data_rows(&self) -> impl Iterator<Item = Row<'_>> {
+        let header = self.rows.first().map(|x| &**x).unwrap_or(&[]);
+
+        self.rows.iter().enumerate().skip(1).map(move |(i, cells)| {
+            let line = self.row_positions.get(i).map(|p| p.0).unwrap_or(0);
+            Row::new(header, cells, line)
+        })
+    }
+}
+
+impl Tag {
+    /// The edit removing this tag from `source`, the text it was parsed from.
+    ///
+    /// Whitespace separating the tag from its neighbours is removed with it, and if it was the
+    /// only tag on its line, the whole line is removed.
+    pub fn removal_edit(&self, source: &str) -> TextEdit {
+        let (start, end) = self.span;
+        let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = source[end..]
+            .find('\n')
+            .map(|i| end + i)
+            .unwrap_or_else(|| source.len());
+
+        let before = &source[line_start..start];
+        let after = source[end..line_end].trim_end_matches('\r');
+        let is_space = |c: char| c == ' ' || c == '\t';
+
+        let span = if before.trim().is_empty() && after.trim().is_empty() {
+            if line_end < source.len() {
+                (line_start, line_end + 1)
+            } else {
+                (line_start.saturating_sub(1), line_end)
+            }
+        } else if after.trim().is_empty() {
+            (
+                start - (before.len() - before.trim_end_matches(is_space).len()),
+                end + after.len(),
+            )
+        } else {
+            (
+                start,
+                end + (after.len() - after.trim_start_matches(is_space).len()),
+            )
+        };
+
+        TextEdit {
+            span,
+            replacement: String::new(),
+        }
+    }
+}
+
+impl Examples {
+    /// The data rows of the examples table, addressable by placeholder name.
+    pub fn rows(&self) -> impl Iterator<Item = ExampleRow<'_>> {
+        self.table.iter().flat_map(|t| t.data_rows())
+    }
+
+    /// The `(line, col)` position of the data row at `index` (not counting the header row) in
+    /// the .feature file, if there is such a row.
+    pub fn row_position(&self, index: usize) -> Option<(usize, usize)> {
+        self.table.as_ref()?.row_positions.get(index + 1).copied()
+    }
+
+    /// A copy of the examples keeping the header row and only the data rows matching `pred`,
+    /// along with their positions.
+    pub fn filter_rows(&self, pred: impl Fn(&ExampleRow<'_>) -> bool) -> Examples {
+        fn kept<T: Clone>(items: &[T], keep: &[bool]) -> Vec<T> {
+            items
+                .iter()
+                .zip(keep)
+                .filter(|(_, keep)| **keep)
+                .map(|(item, _)| item.clone())
+                .collect()
+        }
+
+        let table = self.table.as_ref().map(|table| {
+            let keep: Vec<_> = std::iter::once(true)
+                .chain(table.data_rows().map(|row| pred(&row)))
+                .collect();
+
+            Table {
+                rows: kept(&table.rows, &keep),
+                row_positions: kept(&table.row_positions, &keep),
+                row_comments: kept(&table.row_comments, &keep),
+                row_leading_comments: kept(&table.row_leading_comments, &keep),
+                ..table.clone()
+            }
+        });
+
+        Examples {
+            table,
+            ..self.clone()
+        }
+    }
+}
+
+impl Scenario {
+    /// The number of times the scenario runs: once for each data row of its examples, or once
+    /// if it has none.
+    fn run_count(&self) -> usize {
+        if self.examples.is_empty() {
+            1
+        } else {
+            self.example_count()
+        }
+    }
+
+    /// A copy of the scenario keeping only the examples rows whose `column` is `value`.
+    ///
+    /// It is an error for an examples table not to have `column`. Blocks left without rows are
+    /// kept, and expand to nothing.
+    pub fn filter_examples_by(
+        &self,
+        column: &str,
+        value: &str,
+    ) -> Result<Scenario, CellParseError> {
+        let examples = self
+            .examples
+            .iter()
+            .map(|examples| match &examples.table {
+                Some(table)
+                    if !table
+                        .rows
+                        .first()
+                        .is_some_and(|header| header.iter().any(|c| c == column)) =>
+                {
+                    Err(CellParseError::MissingColumn {
+                        column: column.to_string(),
+                        line: table.position.0,
+                    })
+                }
+                _ => Ok(examples.filter_rows(|row| row.get(column) == Some(value))),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Scenario {
+            examples,
+            ..self.clone()
+        })
+    }
+
+    /// The scenarios described by each data row of the examples, with every `<placeholder>`
+    /// in the name, steps, docstrings and tables replaced by the row's value. Each has the tags
+    /// of the scenario along with those of the examples block its row belongs to.
+    ///
+    /// A scenario without examples expands to itself, while one with only empty examples tables
+    /// expands to nothing.
+    pub fn expand_examples(&self) -> Vec<Scenario> {
+        self.iter_expansions().collect()
+    }
+
+    /// The scenarios of [`expand_examples`](Scenario::expand_examples), with placeholders
+    /// written as set in `options`.
+    pub fn expand_examples_with(&self, options: &ExpandOptions) -> Vec<Scenario> {
+        self.iter_expansions_with(options).collect()
+    }
+
+    /// The scenarios of [`expand_examples`](Scenario::expand_examples), or an error if there
+    /// would be more than `max_expansions` of them, as can happen with very large examples
+    /// tables.
+    pub fn try_expand_examples(
+        &self,
+        max_expansions: usize,
+    ) -> Result<Vec<Scenario>, TooManyExpansions> {
+        let count = self.run_count();
+        if count > max_expansions {
+            return Err(TooManyExpansions {
+                scenario: self.name.clone(),
+                position: self.position,
+                count,
+                max_expansions,
+            });
+        }
+
+        Ok(self.expand_examples())
+    }
+
+    /// The scenarios of [`expand_examples`](Scenario::expand_examples), each expanded only as
+    /// it is reached.
+    pub fn iter_expansions(&self) -> impl Iterator<Item = Scenario> + '_ {
+        self.iter_expansions_with(&ExpandOptions::default())
+    }
+
+    /// The scenarios of [`expand_examples_with`](Scenario::expand_examples_with), each
+    /// expanded only as it is reached.
+    pub fn iter_expansions_with(
+        &self,
+        options: &ExpandOptions,
+    ) -> impl Iterator<Item = Scenario> + '_ {
+        let delimiters = options.placeholder_delimiters().to_vec();
+        let plain = Some(self).filter(|s| s.examples.is_empty()).cloned();
+
+        let expanded = self
+            .examples
             .iter()
-            .next()
-            .map(|x| x.len())
-            .unwrap_or_else(|| 0)
+            .enumerate()
+            .flat_map(|(block, examples)| {
+                let rows = examples.rows().enumerate();
+                rows.map(move |row| (block, examples, row))
+            })
+            .map(move |(block, examples, (i, row))| {
+                let fill = |text: &str| fill_placeholders(text, &row, &delimiters);
+                let steps = self
+                    .steps
+                    .iter()
+                    .map(|step| fill_step(step, &fill))
+                    .collect();
+
+                let mut tags = self.tags.clone();
+                let mut tag_tokens = self.tag_tokens.clone();
+                for (i, tag) in examples.tags.iter().enumerate() {
+                    if !tags.contains(tag) {
+                        tags.push(tag.clone());
+                        tag_tokens.extend(examples.tag_tokens.get(i).cloned());
+                    }
+                }
+
+                Scenario {
+                    name: fill(&self.name),
+                    steps,
+                    examples: vec![],
+                    tags,
+                    tag_tokens,
+                    span: self.span,
+                    position: self.position,
+                    leading_blank_lines: self.leading_blank_lines,
+                    example_row: examples.row_position(i),
+                    example_index: Some((block, i)),
+                    outline: false,
+                }
+            });
+
+        plain.into_iter().chain(expanded)
+    }
+}
+
+fn strip_tags(tags: &mut Vec<String>, tag_tokens: &mut Vec<Tag>, pred: &impl Fn(&str) -> bool) {
+    tags.retain(|t| !pred(t));
+    tag_tokens.retain(|t| !pred(&t.name));
+}
+
+fn normalize_line_endings(text: &mut String) {
+    if text.contains('\r') {
+        *text = text.replace("\r\n", "\n").replace('\r', "\n");
+    }
+}
+
+/// A copy of `step` and its sub-steps with `fill` applied to their values, docstrings and
+/// table cells.
+fn fill_step(step: &Step, fill: &impl Fn(&str) -> String) -> Step {
+    Step {
+        value: fill(&step.value),
+        docstring: step.docstring.as_deref().map(fill),
+        table: step.table.as_ref().map(|t| Table {
+            rows: t
+                .rows
+                .iter()
+                .map(|r| r.iter().map(|c| fill(c)).collect())
+                .collect(),
+            ..t.clone()
+        }),
+        sub_steps: step.sub_steps.iter().map(|s| fill_step(s, fill)).collect(),
+        ..step.clone()
+    }
+}
+
+/// Replaces each placeholder in `text`, written between one of the `delimiters` pairs, that
+/// names a column of `row` with its value.
+fn fill_placeholders(text: &str, row: &Row<'_>, delimiters: &[(String, String)]) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let placeholder = delimiters.iter().find_map(|(open, close)| {
+            let after = rest.strip_prefix(&**open)?;
+            let end = after.find(&**close)?;
+            Some((row.get(&after[..end])?, open.len() + end + close.len()))
+        });
+
+        match placeholder {
+            Some((value, len)) => {
+                out.push_str(value);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    out
+}
+
+/// A parsed feature together with the source text it was parsed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedFeature {
+    /// The parsed feature.
+    pub feature: Feature,
+    /// The original text of the .feature file, kept byte for byte. Writing it back out is the
+    /// way to reproduce the file exactly, as [`Feature::to_gherkin_string`] renders it in a
+    /// canonical form instead.
+    pub source: String,
+    /// Measurements of the parse, if requested with [`ParseOptions::with_metrics`].
+    pub metrics: Option<ParseMetrics>,
+    /// The problems recovered from while parsing, in document order.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ParsedFeature {
+    /// The source text covered by a `(start, end)` span, such as [`Step::span`].
+    ///
+    /// Panics if the span is out of bounds or does not fall on character boundaries.
+    pub fn text_of(&self, span: (usize, usize)) -> &str {
+        &self.source[span.0..span.1]
     }
 }
 
@@ -214,47 +960,2666 @@ pub enum ParseFileError {
     Reading(PathBuf, #[source] std::io::Error),
 
     #[error("Could not parse feature file: {0}")]
-    Parsing(
-        PathBuf,
-        #[source] peg::error::ParseError<peg::str::LineCol>,
-    ),
+    Parsing(PathBuf, #[source] peg::error::ParseError<peg::str::LineCol>),
+}
+
+/// The error from [`Feature::parse_with_options`] and [`Feature::parse_with_source`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseSourceError {
+    #[error("Could not parse feature: {0}")]
+    Parsing(#[source] ParseError<LineCol>),
+
+    /// A table wider than [`ParseOptions::with_max_table_columns`] allows, when strict.
+    #[error("{}:{}: {0}", .0.position.0, .0.position.1)]
+    TableTooWide(#[source] TableTooWide),
+}
+
+/// A parse error in a named file, as returned by [`Feature::try_from_str_with_filename`]
+///
+/// It is displayed as `path:line:col: message`, the form editors and terminals link to.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{}:{}:{}: expected {}", .filename.display(), .error.location.line, .error.location.column, .error.expected)]
+pub struct NamedParseError {
+    /// The name of the file the source was read from.
+    pub filename: PathBuf,
+    /// The error itself.
+    #[source]
+    pub error: ParseError<LineCol>,
+}
+
+/// How [`Feature::merge_backgrounds`] combines two backgrounds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BackgroundMerge {
+    /// Run the first background's steps and then the second's.
+    Concatenate,
+    /// Fail rather than guess. This is the default.
+    #[default]
+    Error,
+    /// Keep the first background and drop the second.
+    PreferFirst,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Both features define a background")]
+pub struct BackgroundConflict;
+
+/// The error from [`Scenario::try_expand_examples`] when a scenario has too many examples rows
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "Scenario {scenario:?} would expand to {count} scenarios, over the limit of {max_expansions}"
+)]
+pub struct TooManyExpansions {
+    /// The name of the scenario.
+    pub scenario: String,
+    /// The `(line, col)` position of the scenario in the .feature file.
+    pub position: (usize, usize),
+    /// The number of scenarios it would expand to.
+    pub count: usize,
+    /// The limit that was exceeded.
+    pub max_expansions: usize,
+}
+
+/// The error from [`Table::check_width`] when a table has more columns than allowed
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("table has {columns} columns, over the limit of {max_columns}")]
+pub struct TableTooWide {
+    /// The `(line, col)` position of the table in the .feature file.
+    pub position: (usize, usize),
+    /// The number of columns the table has.
+    pub columns: usize,
+    /// The limit that was exceeded.
+    pub max_columns: usize,
 }
 
 impl Feature {
     #[inline]
     pub fn parse_path<P: AsRef<Path>>(path: P) -> Result<Feature, ParseFileError> {
-        let s = std::fs::read_to_string(path.as_ref())
-            .map_err(|e| ParseFileError::Reading(path.as_ref().to_path_buf(), e))?;
-        let mut feature = parser::gherkin_parser::feature(&s, &Default::default())
-            .map_err(|e| ParseFileError::Parsing(path.as_ref().to_path_buf(), e))?;
-        feature.path = Some(path.as_ref().to_path_buf());
+        Feature::try_from_path(path)
+    }
+
+    /// Reads and parses the file at `path`, recording it as the feature's `path`.
+    ///
+    /// A leading byte order mark is skipped, and spans are relative to the text after it.
+    pub fn try_from_path<P: AsRef<Path>>(path: P) -> Result<Feature, ParseFileError> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| ParseFileError::Reading(path.to_path_buf(), e))?;
+        let s = s.strip_prefix('\u{feff}').unwrap_or(&s);
+        let mut feature =
+            Feature::parse(s).map_err(|e| ParseFileError::Parsing(path.to_path_buf(), e))?;
+        feature.path = Some(path.to_path_buf());
+        Ok(feature)
+    }
+
+    /// Parses `source`, read from the file `filename`, recording it as the feature's `path`.
+    /// Any error names the file, for reporting the way a compiler would.
+    ///
+    /// A leading byte order mark is skipped, as by [`try_from_path`](Feature::try_from_path).
+    pub fn try_from_str_with_filename<P: AsRef<Path>>(
+        source: &str,
+        filename: P,
+    ) -> Result<Feature, NamedParseError> {
+        let filename = filename.as_ref();
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        let mut feature = Feature::parse(source).map_err(|error| NamedParseError {
+            filename: filename.to_path_buf(),
+            error,
+        })?;
+        feature.path = Some(filename.to_path_buf());
         Ok(feature)
     }
 
+    /// Parses a feature from its source text.
+    ///
+    /// This is the way to parse a feature held in memory: malformed input is reported as an
+    /// error with its line and column rather than a panic.
     #[inline]
     pub fn parse<S: AsRef<str>>(input: S) -> Result<Feature, ParseError<LineCol>> {
-        parser::gherkin_parser::feature(input.as_ref(), &Default::default())
+        let source = input.as_ref();
+        let input = parser::prepare(source, &ParseOptions::default());
+        let mut feature = parser::parse(&input, &parser::GherkinEnv::new(&input))?;
+        feature.line_ending = LineEnding::predominant(source);
+        feature.normalize_line_endings();
+        Ok(feature)
     }
-}
 
-impl Step {
-    pub fn docstring(&self) -> Option<&String> {
-        match &self.docstring {
-            Some(v) => Some(&v),
-            None => None,
+    /// Parses the input, keeping the source text alongside the resulting feature.
+    pub fn parse_with_source(source: String) -> Result<ParsedFeature, ParseSourceError> {
+        Feature::parse_with_options(source, &ParseOptions::default())
+    }
+
+    /// Parses the input as configured by `options`, keeping the source text alongside the
+    /// resulting feature.
+    pub fn parse_with_options(
+        source: String,
+        options: &ParseOptions,
+    ) -> Result<ParsedFeature, ParseSourceError> {
+        let start = options.metrics().then(std::time::Instant::now);
+        let input = parser::prepare(&source, options);
+        let env = parser::GherkinEnv::with_options(&input, options.clone());
+        let mut feature = parser::parse(&input, &env).map_err(|e| match env.table_too_wide() {
+            Some(error) => ParseSourceError::TableTooWide(error),
+            None => ParseSourceError::Parsing(e),
+        })?;
+        let mut diagnostics = env.diagnostics();
+        include::splice(&mut feature, &source, options, &mut diagnostics);
+        feature.line_ending = LineEnding::predominant(&source);
+        feature.normalize_line_endings();
+        let metrics =
+            start.map(|t| ParseMetrics::new(&feature, &diagnostics, source.len(), t.elapsed()));
+
+        Ok(ParsedFeature {
+            feature,
+            source,
+            metrics,
+            diagnostics,
+        })
+    }
+
+    /// A hash of the feature's contents that is stable across runs and platforms.
+    ///
+    /// The `path` is not included, so the same file checked out in two places hashes
+    /// identically. The value may still change between crate versions.
+    pub fn stable_hash(&self) -> u64 {
+        let Feature {
+            name,
+            description,
+            background,
+            scenarios,
+            rules,
+            tags,
+            tag_tokens,
+            span,
+            position,
+            path: _,
+            trailing_newline,
+            line_ending,
+        } = self;
+
+        let mut hasher = hash::StableHasher::default();
+        (
+            name,
+            description,
+            background,
+            scenarios,
+            rules,
+            tags,
+            tag_tokens,
+            span,
+            position,
+            trailing_newline,
+            line_ending,
+        )
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Combines the background of `other` into this feature's, as decided by `policy` when both
+    /// have one. If only one of them has a background, that one is used.
+    pub fn merge_backgrounds(
+        &mut self,
+        other: &Feature,
+        policy: BackgroundMerge,
+    ) -> Result<(), BackgroundConflict> {
+        match (&mut self.background, &other.background) {
+            (_, None) => {}
+            (None, Some(b)) => self.background = Some(b.clone()),
+            (Some(a), Some(b)) => match policy {
+                BackgroundMerge::Concatenate => a.steps.extend(b.steps.iter().cloned()),
+                BackgroundMerge::Error => return Err(BackgroundConflict),
+                BackgroundMerge::PreferFirst => {}
+            },
         }
+
+        Ok(())
     }
 
-    pub fn table(&self) -> Option<&Table> {
-        match &self.table {
-            Some(v) => Some(&v),
-            None => None,
+    /// Removes the backgrounds of the feature and its rules, placing a copy of their steps
+    /// before the steps of every scenario they apply to: the feature's first, then the rule's.
+    /// Outlines receive them as they are, before their own steps.
+    ///
+    /// The copies have [`Step::from_background`] set, and every span and position within them
+    /// cleared, including those of their docstrings, tables and sub-steps, as they no longer
+    /// appear where they were written.
+    pub fn inline_background(&mut self) {
+        fn inlined_step(step: Step) -> Step {
+            Step {
+                span: (0, 0),
+                position: (0, 0),
+                docstring_span: step.docstring_span.map(|_| (0, 0)),
+                table: step.table.map(|table| Table {
+                    span: (0, 0),
+                    position: (0, 0),
+                    row_positions: vec![(0, 0); table.row_positions.len()],
+                    ..table
+                }),
+                sub_steps: step.sub_steps.into_iter().map(inlined_step).collect(),
+                leading_blank_lines: 0,
+                from_background: true,
+                ..step
+            }
+        }
+
+        fn inlined(background: Background) -> Vec<Step> {
+            background.steps.into_iter().map(inlined_step).collect()
+        }
+
+        for rule in self.rules.iter_mut() {
+            let (backgrounds, children) = std::mem::take(&mut rule.children)
+                .into_iter()
+                .partition(|c| matches!(c, RuleChild::Background(_)));
+            rule.children = children;
+
+            for background in backgrounds {
+                if let RuleChild::Background(background) = background {
+                    let steps = inlined(background);
+                    for scenario in rule.scenarios_mut() {
+                        scenario.steps.splice(0..0, steps.iter().cloned());
+                    }
+                }
+            }
+        }
+
+        let steps = match self.background.take() {
+            Some(background) => inlined(background),
+            None => return,
+        };
+        let scenarios = self
+            .scenarios
+            .iter_mut()
+            .chain(self.rules.iter_mut().flat_map(Rule::scenarios_mut));
+        for scenario in scenarios {
+            scenario.steps.splice(0..0, steps.iter().cloned());
         }
     }
-}
 
-impl std::fmt::Display for Step {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {}", &self.raw_type, &self.value)
+    /// A copy of the feature with every step, in the background and all scenarios including
+    /// outline templates and those in rules, replaced by `f` applied to it.
+    ///
+    /// The new steps are used as returned, so they keep whatever span and position `f` gives
+    /// them. The sub-steps of each new step are then replaced in turn.
+    pub fn map_steps<F: Fn(&Step) -> Step>(&self, f: F) -> Feature {
+        let mut feature = self.clone();
+        feature.for_each_step(|step| *step = f(step));
+
+        feature
+    }
+
+    /// The background, scenarios and rules of the feature, in the order they appear in the
+    /// .feature file.
+    pub fn children(&self) -> impl Iterator<Item = FeatureChild<'_>> {
+        let mut children: Vec<_> = self
+            .background
+            .iter()
+            .map(FeatureChild::Background)
+            .chain(self.scenarios.iter().map(FeatureChild::Scenario))
+            .chain(self.rules.iter().map(FeatureChild::Rule))
+            .collect();
+        children.sort_by_key(|c| c.span().0);
+        children.into_iter()
+    }
+
+    /// A copy of the feature keeping only the scenarios whose tags, including those inherited
+    /// from the feature and its rules, match `expression`.
+    ///
+    /// Tags on an examples block only apply to the rows of that block, so an outline is kept
+    /// with just the examples blocks that match. Rules left without scenarios are removed.
+    pub fn filter_by_expression(&self, expression: &TagOperation) -> Feature {
+        let filter = |scenario: &Scenario, inherited: &[&str]| -> Option<Scenario> {
+            let mut tags = inherited.to_vec();
+            tags.extend(scenario.tags.iter().map(|t| &**t));
+
+            if scenario.examples.is_empty() {
+                return Some(scenario.clone()).filter(|_| expression.matches(&tags));
+            }
+
+            let examples: Vec<_> = scenario
+                .examples
+                .iter()
+                .filter(|e| {
+                    let mut tags = tags.clone();
+                    tags.extend(e.tags.iter().map(|t| &**t));
+                    expression.matches(&tags)
+                })
+                .cloned()
+                .collect();
+
+            Some(Scenario {
+                examples,
+                ..scenario.clone()
+            })
+            .filter(|s| !s.examples.is_empty())
+        };
+        let feature_tags: Vec<&str> = self.tags.iter().map(|t| &**t).collect();
+
+        let rules = self
+            .rules
+            .iter()
+            .filter_map(|rule| {
+                let mut tags = feature_tags.clone();
+                tags.extend(rule.tags.iter().map(|t| &**t));
+
+                let children = rule
+                    .children
+                    .iter()
+                    .filter_map(|child| match child {
+                        RuleChild::Background(_) => Some(child.clone()),
+                        RuleChild::Scenario(s) => filter(s, &tags).map(RuleChild::Scenario),
+                    })
+                    .collect();
+
+                Some(Rule {
+                    children,
+                    ..rule.clone()
+                })
+                .filter(|r| r.scenarios().next().is_some())
+            })
+            .collect();
+
+        Feature {
+            scenarios: self
+                .scenarios
+                .iter()
+                .filter_map(|s| filter(s, &feature_tags))
+                .collect(),
+            rules,
+            ..self.clone()
+        }
+    }
+
+    /// Every scenario in the feature, including those nested in rules, sorted by `key`.
+    ///
+    /// Scenarios with equal keys keep their document order, and those without a key come last.
+    /// The feature itself is not modified. See [`ScenarioExt::tag_number`] for ordering by tags
+    /// such as `@order-10`.
+    pub fn ordered_scenarios(&self, key: impl Fn(&Scenario) -> Option<i64>) -> Vec<&Scenario> {
+        let mut scenarios: Vec<_> = self
+            .children()
+            .flat_map(|c| match c {
+                FeatureChild::Background(_) => vec![],
+                FeatureChild::Rule(r) => r.scenarios().collect(),
+                FeatureChild::Scenario(s) => vec![s],
+            })
+            .map(|s| (key(s), s))
+            .collect();
+        scenarios.sort_by_key(|(k, _)| (k.is_none(), *k));
+        scenarios.into_iter().map(|(_, s)| s).collect()
+    }
+
+    /// Sorts the scenarios of the feature, and those of each rule, by name. Scenarios with the
+    /// same name keep their order.
+    pub fn sort_scenarios_by_name(&mut self) {
+        self.scenarios.sort_by(|a, b| a.name.cmp(&b.name));
+        for rule in self.rules.iter_mut() {
+            rule.sort_scenarios_by_key(|s| s.name.clone());
+        }
+    }
+
+    /// Sorts the scenarios of the feature, and those of each rule, by their position in the
+    /// .feature file, restoring the order they were parsed in.
+    pub fn sort_scenarios_by_position(&mut self) {
+        self.scenarios.sort_by_key(|s| s.position);
+        for rule in self.rules.iter_mut() {
+            rule.sort_scenarios_by_key(|s| s.position);
+        }
+    }
+
+    /// The steps of the background, or none if there is no background.
+    pub fn background_steps(&self) -> &[Step] {
+        self.background.as_ref().map_or(&[], |b| &b.steps)
+    }
+
+    /// Converts any `\r\n` or lone `\r` left in docstrings, descriptions and step values to
+    /// `\n`, so features parsed from files with different line endings compare equal.
+    ///
+    /// Spans still refer to the original source. Normalizing twice has no further effect.
+    pub fn normalize_line_endings(&mut self) {
+        let descriptions = std::iter::once(&mut self.description)
+            .chain(self.rules.iter_mut().map(|r| &mut r.description))
+            .flatten();
+        for description in descriptions {
+            normalize_line_endings(description);
+        }
+
+        self.for_each_step(|step| {
+            normalize_line_endings(&mut step.value);
+            if let Some(docstring) = &mut step.docstring {
+                normalize_line_endings(docstring);
+            }
+        });
+    }
+
+    /// Removes the comments kept on and between table rows, in step and examples tables alike.
+    pub fn strip_comments(&mut self) {
+        self.for_each_table(|table| {
+            table.row_comments.iter_mut().for_each(|c| *c = None);
+            table.row_leading_comments.iter_mut().for_each(Vec::clear);
+        });
+    }
+
+    /// Sets every span and position in the feature to `(0, 0)`, including those of tags, table
+    /// rows, docstrings and the examples row of an expanded scenario, for output where they are
+    /// noise.
+    pub fn strip_positions(&mut self) {
+        self.for_each_location(|span, position| {
+            *span = (0, 0);
+            *position = (0, 0);
+        });
+
+        for scenario in self.scenarios_mut() {
+            scenario.example_row = scenario.example_row.map(|_| (0, 0));
+        }
+
+        self.for_each_step(|step| {
+            step.docstring_span = step.docstring_span.map(|_| (0, 0));
+        });
+
+        self.for_each_table(|table| {
+            table.row_positions.iter_mut().for_each(|p| *p = (0, 0));
+        });
+    }
+
+    /// A copy of the feature with every position cleared, as by
+    /// [`strip_positions`](Feature::strip_positions).
+    pub fn without_positions(&self) -> Feature {
+        let mut feature = self.clone();
+        feature.strip_positions();
+        feature
+    }
+
+    /// Every step in the feature, including those of rule backgrounds, in document order.
+    fn steps_mut(&mut self) -> impl Iterator<Item = &mut Step> {
+        self.background
+            .iter_mut()
+            .flat_map(|b| b.steps.iter_mut())
+            .chain(self.scenarios.iter_mut().flat_map(|s| s.steps.iter_mut()))
+            .chain(self.rules.iter_mut().flat_map(Rule::steps_mut))
+    }
+
+    /// Calls `f` with every step in the feature, as given by `steps_mut`, each followed by its
+    /// sub-steps.
+    fn for_each_step(&mut self, mut f: impl FnMut(&mut Step)) {
+        for step in self.steps_mut() {
+            f(step);
+            step.for_each_sub_step(&mut f);
+        }
+    }
+
+    /// Every scenario in the feature, including those in rules, in document order.
+    fn scenarios_mut(&mut self) -> impl Iterator<Item = &mut Scenario> {
+        self.scenarios
+            .iter_mut()
+            .chain(self.rules.iter_mut().flat_map(Rule::scenarios_mut))
+    }
+
+    /// Calls `f` with the `(start, end)` span and `(line, col)` position of every node, tags
+    /// included.
+    fn for_each_location(&mut self, mut f: impl FnMut(&mut (usize, usize), &mut (usize, usize))) {
+        fn tags(tags: &mut [Tag], f: &mut impl FnMut(&mut (usize, usize), &mut (usize, usize))) {
+            for tag in tags {
+                f(&mut tag.span, &mut tag.position);
+            }
+        }
+
+        f(&mut self.span, &mut self.position);
+        tags(&mut self.tag_tokens, &mut f);
+
+        if let Some(background) = &mut self.background {
+            f(&mut background.span, &mut background.position);
+        }
+
+        for rule in self.rules.iter_mut() {
+            f(&mut rule.span, &mut rule.position);
+            tags(&mut rule.tag_tokens, &mut f);
+
+            if let Some(background) = rule.background_mut() {
+                f(&mut background.span, &mut background.position);
+            }
+        }
+
+        for scenario in self.scenarios_mut() {
+            f(&mut scenario.span, &mut scenario.position);
+            tags(&mut scenario.tag_tokens, &mut f);
+
+            for examples in scenario.examples.iter_mut() {
+                f(&mut examples.span, &mut examples.position);
+                tags(&mut examples.tag_tokens, &mut f);
+            }
+        }
+
+        self.for_each_step(|step| f(&mut step.span, &mut step.position));
+
+        self.for_each_table(|table| f(&mut table.span, &mut table.position));
+    }
+
+    /// Ends the spans reaching past `end` at `end`, so that the spans of the last nodes do not
+    /// depend on whether the file ends with a newline.
+    pub(crate) fn end_spans_at(&mut self, end: usize) {
+        self.for_each_location(|span, _| {
+            span.0 = span.0.min(end);
+            span.1 = span.1.min(end);
+        });
+    }
+
+    /// Calls `f` with every step and examples table in the feature.
+    fn for_each_table(&mut self, mut f: impl FnMut(&mut Table)) {
+        self.for_each_step(|step| step.table.iter_mut().for_each(&mut f));
+
+        for examples in self.scenarios_mut().flat_map(|s| s.examples.iter_mut()) {
+            examples.table.iter_mut().for_each(&mut f);
+        }
+    }
+
+    pub(crate) fn all_scenarios(&self) -> impl Iterator<Item = &Scenario> {
+        self.scenarios
+            .iter()
+            .chain(self.rules.iter().flat_map(Rule::scenarios))
+    }
+
+    /// Every step in the feature, including those of rule backgrounds, in document order, each
+    /// followed by its sub-steps.
+    pub(crate) fn all_steps(&self) -> impl Iterator<Item = &Step> {
+        self.background
+            .iter()
+            .flat_map(|b| b.steps.iter())
+            .chain(self.scenarios.iter().flat_map(|s| s.steps.iter()))
+            .chain(self.rules.iter().flat_map(Rule::steps))
+            .flat_map(Step::with_sub_steps)
+    }
+
+    pub(crate) fn rule_of(&self, scenario: &Scenario) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .find(|r| r.scenarios().any(|s| std::ptr::eq(s, scenario)))
+    }
+
+    /// Removes the tags matching `pred` from the feature, its rules, scenarios and examples,
+    /// along with their entries in `tag_tokens`. A node left without tags has empty `tags`.
+    pub fn strip_tags<F: Fn(&str) -> bool>(&mut self, pred: F) {
+        strip_tags(&mut self.tags, &mut self.tag_tokens, &pred);
+
+        for rule in self.rules.iter_mut() {
+            strip_tags(&mut rule.tags, &mut rule.tag_tokens, &pred);
+        }
+
+        let scenarios = self
+            .scenarios
+            .iter_mut()
+            .chain(self.rules.iter_mut().flat_map(Rule::scenarios_mut));
+        for scenario in scenarios {
+            strip_tags(&mut scenario.tags, &mut scenario.tag_tokens, &pred);
+            for examples in scenario.examples.iter_mut() {
+                strip_tags(&mut examples.tags, &mut examples.tag_tokens, &pred);
+            }
+        }
+    }
+}
+
+/// Combines `features` into a single feature called `name`, with the scenarios of every
+/// feature followed by the rules of every feature.
+///
+/// If every feature has the same background, it is kept once. Otherwise each feature's
+/// background is inlined into its scenarios first, as by [`Feature::inline_background`]. The
+/// tags of each feature are added to its scenarios and rules, so the tags that apply to each
+/// scenario are unchanged. The spans of the scenarios and rules are cleared, as they come from
+/// different files.
+pub fn concat(features: Vec<Feature>, name: &str) -> Feature {
+    let shared = features
+        .first()
+        .and_then(|f| f.background.clone())
+        .filter(|b| {
+            features.iter().all(|f| match &f.background {
+                Some(other) => same_steps(&b.steps, &other.steps),
+                None => false,
+            })
+        });
+
+    let mut scenarios = vec![];
+    let mut rules = vec![];
+
+    for mut feature in features {
+        if shared.is_some() {
+            feature.background = None;
+        } else {
+            feature.inline_background();
+        }
+
+        let (feature_tags, feature_tokens) = (&feature.tags, &feature.tag_tokens);
+        let inherit = |tags: &mut Vec<String>, tag_tokens: &mut Vec<Tag>| {
+            for (i, tag) in feature_tags.iter().enumerate().rev() {
+                if !tags.contains(tag) {
+                    tags.insert(0, tag.clone());
+                    if let Some(token) = feature_tokens.get(i) {
+                        tag_tokens.insert(0, token.clone());
+                    }
+                }
+            }
+        };
+
+        for mut scenario in std::mem::take(&mut feature.scenarios) {
+            inherit(&mut scenario.tags, &mut scenario.tag_tokens);
+            scenario.span = (0, 0);
+            scenarios.push(scenario);
+        }
+
+        for mut rule in std::mem::take(&mut feature.rules) {
+            inherit(&mut rule.tags, &mut rule.tag_tokens);
+            rule.span = (0, 0);
+            rules.push(rule);
+        }
+    }
+
+    Feature::builder()
+        .name(name.to_string())
+        .background(shared)
+        .scenarios(scenarios)
+        .rules(rules)
+        .build()
+}
+
+/// The `path:line` entry naming `scenario` of `feature` in a Cucumber rerun file, so that only
+/// it (or only its examples row) runs again. Without a path, just the line is given.
+///
+/// The line is resolved back to the scenario by [`FeatureExt::scenario_or_row_at_line`].
+pub fn rerun_entry(feature: &Feature, scenario: &Scenario) -> String {
+    match &feature.path {
+        Some(path) => format!("{}:{}", path.display(), scenario.source_line()),
+        None => scenario.source_line().to_string(),
+    }
+}
+
+/// Whether two lists of steps say the same thing, ignoring where they were written.
+fn same_steps(a: &[Step], b: &[Step]) -> bool {
+    let table = |t: &Option<Table>| t.as_ref().map(|t| t.rows.clone());
+
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| {
+            a.ty == b.ty
+                && a.raw_type == b.raw_type
+                && a.value == b.value
+                && a.docstring == b.docstring
+                && table(&a.table) == table(&b.table)
+        })
+}
+
+impl Step {
+    #[deprecated(note = "use the `docstring` field or `StepExt::docstring_text`")]
+    pub fn docstring(&self) -> Option<&String> {
+        match &self.docstring {
+            Some(v) => Some(v),
+            None => None,
+        }
+    }
+
+    #[deprecated(note = "use the `table` field or `StepExt::data_table`")]
+    pub fn table(&self) -> Option<&Table> {
+        match &self.table {
+            Some(v) => Some(v),
+            None => None,
+        }
+    }
+
+    /// The step followed by its sub-steps, and each of theirs in turn.
+    fn with_sub_steps(&self) -> Box<dyn Iterator<Item = &Step> + '_> {
+        Box::new(std::iter::once(self).chain(self.sub_steps.iter().flat_map(Step::with_sub_steps)))
+    }
+
+    /// Calls `f` with each of the step's sub-steps, and each of theirs in turn.
+    fn for_each_sub_step(&mut self, f: &mut impl FnMut(&mut Step)) {
+        for step in self.sub_steps.iter_mut() {
+            f(step);
+            step.for_each_sub_step(f);
+        }
+    }
+}
+
+/// A background, rule or scenario directly inside a feature, as yielded by [`Feature::children`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureChild<'a> {
+    Background(&'a Background),
+    Rule(&'a Rule),
+    Scenario(&'a Scenario),
+}
+
+/// The feature or rule directly containing a scenario, as returned by [`FeatureExt::parent_of`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScenarioParent<'a> {
+    Feature(&'a Feature),
+    Rule(&'a Rule),
+}
+
+impl FeatureChild<'_> {
+    /// The `(start, end)` offset the child was found in the .feature file.
+    pub fn span(&self) -> (usize, usize) {
+        match self {
+            FeatureChild::Background(b) => b.span,
+            FeatureChild::Rule(r) => r.span,
+            FeatureChild::Scenario(s) => s.span,
+        }
+    }
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", &self.raw_type, &self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAGGED: &str = r"@billing
+Feature: Invoices
+
+@smoke @slow
+Scenario: Paying an invoice
+  Given an invoice
+
+@smoke
+Rule: Refunds
+  @refund
+  Scenario: Refunding an invoice
+    Given a paid invoice
+";
+
+    #[test]
+    fn scenario_names_and_tag_set() {
+        let feature = Feature::parse(TAGGED).unwrap();
+        assert_eq!(
+            feature.scenario_names(),
+            vec!["Paying an invoice", "Refunding an invoice"]
+        );
+        assert_eq!(
+            feature.tag_set().into_iter().collect::<Vec<_>>(),
+            vec!["billing", "refund", "slow", "smoke"]
+        );
+    }
+
+    #[test]
+    fn examples_row_positions() {
+        let feature = Feature::parse(
+            r"Feature: Positions
+Scenario Outline: eating
+  Given there are <start> cucumbers
+
+  Examples:
+    | start |
+    | 12    |
+
+    | 20    |
+",
+        )
+        .unwrap();
+        let examples = &feature.scenarios[0].examples[0];
+        let (first, second) = (examples.row_position(0), examples.row_position(1));
+
+        assert_eq!(first, Some((7, 5)));
+        assert!(second.unwrap().0 > first.unwrap().0);
+        assert_eq!(examples.row_position(2), None);
+    }
+
+    #[test]
+    fn typed_example_values() {
+        let feature = Feature::parse(
+            r"Feature: Typed examples
+Scenario Outline: eating
+  Given there are <start> cucumbers
+  Then hungry is <hungry>
+
+  Examples:
+    | start | hungry | ratio |
+    |    12 | yes    |   0.5 |
+    |   1e3 | maybe  |   1.5 |
+",
+        )
+        .unwrap();
+        let examples = &feature.scenarios[0].examples[0];
+        let rows = examples.rows().collect::<Vec<_>>();
+
+        assert_eq!(rows[0].get_i64("start"), Ok(12));
+        assert_eq!(rows[0].get_bool("hungry"), Ok(true));
+        assert_eq!(rows[1].get_f64("ratio"), Ok(1.5));
+
+        match rows[1].get_i64("start") {
+            Err(CellParseError::Invalid {
+                column, line, text, ..
+            }) => {
+                assert_eq!(column, "start");
+                assert_eq!(line, 9);
+                assert_eq!(text, "1e3");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(rows[1].get_bool("hungry").is_err());
+        assert_eq!(
+            rows[0].get_i64("missing"),
+            Err(CellParseError::MissingColumn {
+                column: "missing".to_string(),
+                line: 8
+            })
+        );
+    }
+
+    #[test]
+    fn empty_examples_expand_to_nothing() {
+        let feature = Feature::parse(
+            r"Feature: Empty examples
+  Scenario Outline: Bare
+    Given <n> cucumbers
+
+    Examples:
+
+  Scenario Outline: Header only
+    Given <n> cucumbers
+
+    Examples:
+      | n |
+",
+        )
+        .unwrap();
+        let bare = &feature.scenarios[0];
+        let header_only = &feature.scenarios[1];
+
+        assert_eq!(bare.examples[0].table, None);
+        let table = header_only.examples[0].table.as_ref().unwrap();
+        assert_eq!(table.rows, vec![vec!["n"]]);
+
+        for scenario in [bare, header_only] {
+            assert_eq!(scenario.example_count(), 0);
+            assert!(scenario.expand_examples().is_empty());
+        }
+    }
+
+    #[test]
+    fn examples_expand_placeholders() {
+        let feature = Feature::parse(
+            r"Feature: Expansion
+  @outline
+  Scenario Outline: Eating <eat>
+    Given there are <start> cucumbers
+      | left  |
+      | <end> |
+    When I eat <eat> of <unknown>
+
+    @examples
+    Examples:
+      | start | eat | end |
+      | 12    | 5   | 7   |
+      | 20    | 5   | 15  |
+",
+        )
+        .unwrap();
+        let scenario = &feature.scenarios[0];
+        let expanded = scenario.expand_examples();
+
+        assert_eq!(scenario.example_count(), 2);
+        assert_eq!(expanded.len(), 2);
+        assert_eq!(expanded[0].name, "Eating 5");
+        assert_eq!(expanded[0].tags, vec!["outline", "examples"]);
+        assert!(expanded[0].examples.is_empty());
+        assert_eq!(expanded[1].steps[0].value, "there are 20 cucumbers");
+        assert_eq!(
+            expanded[1].steps[0].table.as_ref().unwrap().rows[1],
+            vec!["15"]
+        );
+        assert_eq!(expanded[1].steps[1].value, "I eat 5 of <unknown>");
+    }
+
+    #[test]
+    fn outlines_keep_their_placeholders() {
+        let feature = Feature::parse(
+            r#"Feature: Outlines
+  @outline
+  Scenario Outline: Ordering <count>
+    Given a menu
+      | dish   | price   |
+      | <dish> | <price> |
+    When I order <count> of them
+    Then the receipt reads
+      """
+      <count> x <dish>
+      """
+
+    @lunch
+    Examples:
+      | count | dish  | price |
+      | 2     | soup  | 4     |
+
+  Scenario Outline: Fixed
+    Given a menu
+
+    Examples:
+      | unused |
+      | 1      |
+
+  Scenario: Plain
+    Given a menu
+"#,
+        )
+        .unwrap();
+        let outline = &feature.scenarios[0];
+
+        assert!(outline.outline);
+        assert_eq!(outline.tags, vec!["outline"]);
+        assert_eq!(outline.examples[0].tags, vec!["lunch"]);
+        assert_eq!(
+            outline.examples[0].table.as_ref().unwrap().rows[1],
+            vec!["2", "soup", "4"]
+        );
+        assert_eq!(
+            outline.steps[0].table.as_ref().unwrap().rows[1],
+            vec!["<dish>", "<price>"]
+        );
+        assert_eq!(outline.steps[1].value, "I order <count> of them");
+        assert_eq!(
+            outline.steps[2].docstring.as_deref(),
+            Some("\n<count> x <dish>\n")
+        );
+        assert!(!outline.expand_examples()[0].outline);
+
+        let fixed = &feature.scenarios[1];
+        assert!(fixed.outline);
+        assert_eq!(fixed.steps[0].value, "a menu");
+        assert_eq!(fixed.expand_examples()[0].steps, fixed.steps);
+
+        assert!(!feature.scenarios[2].outline);
+    }
+
+    #[test]
+    fn placeholder_delimiters_are_configurable() {
+        let feature = Feature::parse(
+            r"Feature: Delimiters
+  Scenario Outline: Legacy
+    Given <<a>> and ${a} and <${a}> and ${<a>}
+    And <a and ${b
+
+    Examples:
+      | a | <a |
+      | 1 | 2  |
+",
+        )
+        .unwrap();
+        let scenario = &feature.scenarios[0];
+        let values = |options: &ExpandOptions| -> Vec<String> {
+            let expanded = scenario.expand_examples_with(options);
+            expanded[0].steps.iter().map(|s| s.value.clone()).collect()
+        };
+
+        assert_eq!(
+            values(&ExpandOptions::default()),
+            vec!["2> and ${a} and <${a}> and ${1}", "<a and ${b"]
+        );
+        assert_eq!(
+            values(
+                &ExpandOptions::default()
+                    .with_placeholder_delimiters(vec![("<", ">"), ("${", "}")])
+            ),
+            vec!["2> and 1 and <1> and ${1}", "<a and ${b"]
+        );
+        assert_eq!(
+            values(&ExpandOptions::default().with_placeholder_delimiters(vec![("${", "}")])),
+            vec!["<<a>> and 1 and <1> and ${<a>}", "<a and ${b"]
+        );
+        assert_eq!(
+            values(&ExpandOptions::default().with_placeholder_delimiters(vec![("", "")])),
+            vec!["<<a>> and ${a} and <${a}> and ${<a>}", "<a and ${b"]
+        );
+    }
+
+    #[test]
+    fn errors_name_the_file() {
+        let path = Path::new("features/broken.feature");
+        let err = Feature::try_from_str_with_filename(
+            "Feature: A\n  Scenario: B\n    Given a\n      | x\n",
+            path,
+        )
+        .unwrap_err();
+        assert_eq!(err.filename, path);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "features/broken.feature:5:1: expected {}",
+                err.error.expected
+            )
+        );
+
+        let feature = Feature::try_from_str_with_filename("\u{feff}Feature: A\n", path).unwrap();
+        assert_eq!(feature.name, "A");
+        assert_eq!(feature.path.as_deref(), Some(path));
+    }
+
+    #[test]
+    fn try_from_path_strips_bom() {
+        let source = include_str!("../tests/test.feature");
+        let dir =
+            std::env::temp_dir().join(format!("gherkin-try-from-path-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.feature");
+        std::fs::write(&path, format!("\u{feff}{}", source)).unwrap();
+
+        let feature = Feature::try_from_path(&path).unwrap();
+        assert_eq!(feature.path.as_deref(), Some(&*path));
+        assert_eq!(
+            Feature {
+                path: None,
+                ..feature
+            },
+            Feature::parse(source).unwrap()
+        );
+
+        let missing = dir.join("missing.feature");
+        let err = Feature::try_from_path(&missing).unwrap_err();
+        assert!(matches!(err, ParseFileError::Reading(p, _) if p == missing));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parsed_feature_slices_source() {
+        let parsed = Feature::parse_with_source(TAGGED.to_string()).unwrap();
+        let step = &parsed.feature.rules[0].scenarios().next().unwrap().steps[0];
+        assert_eq!(parsed.text_of(step.span), "Given a paid invoice");
+        assert_eq!(parsed.metrics, None);
+    }
+
+    fn parse_in(mode: ParseMode, source: &str) -> Result<ParsedFeature, ParseError<LineCol>> {
+        Feature::parse_with_options(source.to_string(), &ParseOptions::default().with_mode(mode))
+            .map_err(|e| match e {
+                ParseSourceError::Parsing(e) => e,
+                e => panic!("expected a parse error, got {:?}", e),
+            })
+    }
+
+    #[test]
+    fn strict_mode_rejects_ragged_tables() {
+        let source = "Feature: Tables\n  Scenario: Ragged\n    Given rows\n      | a | b |\n      | 1 | 2 | 3 |\n";
+        let parsed = parse_in(ParseMode::Permissive, source).unwrap();
+        let table = parsed.feature.scenarios[0].steps[0].table.as_ref().unwrap();
+        assert_eq!(table.rows[1], vec!["1", "2"]);
+        assert!(parse_in(ParseMode::Strict, source).is_err());
+    }
+
+    #[test]
+    fn tables_wider_than_the_limit() {
+        let source = "Feature: Wide\n  Scenario: Matrix\n    Given a matrix\n      | a | b | c | d |\n      | 1 | 2 | 3 | 4 |\n";
+        let options = ParseOptions::default().with_max_table_columns(3);
+
+        let parsed = Feature::parse_with_options(source.to_string(), &options).unwrap();
+        let table = parsed.feature.scenarios[0].steps[0].table.as_ref().unwrap();
+        assert_eq!(table.dimensions(), (2, 4));
+        assert_eq!(
+            table.check_width(3),
+            Err(TableTooWide {
+                position: (4, 7),
+                columns: 4,
+                max_columns: 3,
+            })
+        );
+        assert_eq!(table.check_width(4), Ok(()));
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].severity, Severity::Error);
+        assert_eq!(parsed.diagnostics[0].position.0, 4);
+        assert_eq!(
+            parsed.diagnostics[0].message,
+            "table has 4 columns, over the limit of 3"
+        );
+
+        let strict = options.with_mode(ParseMode::Strict);
+        let error = Feature::parse_with_options(source.to_string(), &strict).unwrap_err();
+        assert_eq!(
+            error,
+            ParseSourceError::TableTooWide(TableTooWide {
+                position: (4, 7),
+                columns: 4,
+                max_columns: 3,
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            "4:7: table has 4 columns, over the limit of 3"
+        );
+
+        let options = ParseOptions::default().with_max_table_columns(4);
+        let parsed = Feature::parse_with_options(source.to_string(), &options).unwrap();
+        assert!(parsed.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn final_newline_does_not_move_spans() {
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let with = Feature::parse_path(fixtures.join("trailing_newline.feature")).unwrap();
+        let mut without =
+            Feature::parse_path(fixtures.join("trailing_newline_missing.feature")).unwrap();
+
+        assert!(with.trailing_newline);
+        assert!(!without.trailing_newline);
+        let table = with.scenarios[0].steps[0].table.as_ref().unwrap();
+        assert_eq!(with.span.1, table.span.1);
+
+        without.trailing_newline = true;
+        without.path = with.path.clone();
+        assert_eq!(without, with);
+    }
+
+    #[test]
+    fn strict_mode_rejects_formal_language() {
+        let source = "# language: formal\nSection: Proofs\n  Proof: Trivial\n    Given a thing\n";
+        assert!(parse_in(ParseMode::Permissive, source).is_ok());
+        assert!(parse_in(ParseMode::Strict, source).is_err());
+    }
+
+    #[test]
+    fn keyword_case_is_matched_unless_ignored() {
+        let source = "Feature: Case\n  Scenario: Lowercase\n    given a thing\n    and another\n";
+        for mode in [ParseMode::Permissive, ParseMode::Strict].iter() {
+            assert!(parse_in(*mode, source).is_err());
+        }
+
+        let options = ParseOptions::default().with_case_insensitive_keywords(true);
+        let parsed = Feature::parse_with_options(source.to_string(), &options).unwrap();
+        let steps = &parsed.feature.scenarios[0].steps;
+        assert_eq!(steps[0].ty, StepType::Given);
+        assert_eq!(steps[1].raw_type, "and");
+
+        let options = options.with_normalized_keyword_case(true);
+        let parsed = Feature::parse_with_options(source.to_string(), &options).unwrap();
+        let steps = &parsed.feature.scenarios[0].steps;
+        assert_eq!(steps[0].raw_type, "Given");
+        assert_eq!(steps[1].raw_type, "And");
+    }
+
+    #[test]
+    fn feature_level_examples_are_flagged() {
+        let source = r"Feature: Shared examples
+  Scenario Outline: Adding
+    Given <a> plus <b>
+
+  Scenario Outline: Multiplying
+    Given <a> times <b>
+
+Examples:
+  | a | b |
+  | 1 | 2 |
+";
+        let parsed = Feature::parse_with_source(source.to_string()).unwrap();
+        let scenarios = &parsed.feature.scenarios;
+
+        assert!(scenarios[0].examples.is_empty());
+        assert_eq!(scenarios[1].examples.len(), 1);
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].code, "dedented-examples");
+        assert_eq!(parsed.diagnostics[0].position, (8, 1));
+        assert!(parsed.diagnostics[0].message.contains("\"Multiplying\""));
+
+        let indented = source.replace("\nExamples:\n  |", "\n    Examples:\n      |");
+        let parsed = Feature::parse_with_source(indented).unwrap();
+        assert!(parsed.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn permissive_mode_skips_broken_table_rows() {
+        let source = r"Feature: Salvage
+  Scenario Outline: Rows
+    Given <n>
+
+    Examples:
+      | n |
+      | 1 |
+      | 2 | oops
+      | 3 |
+";
+        let parsed = parse_in(ParseMode::Permissive, source).unwrap();
+        let examples = &parsed.feature.scenarios[0].examples[0];
+        let values: Vec<_> = examples.rows().map(|r| r.get("n").unwrap()).collect();
+
+        assert_eq!(values, vec!["1", "3"]);
+        assert_eq!(examples.row_position(1), Some((9, 7)));
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].position, (8, 7));
+        assert_eq!(parsed.text_of(parsed.diagnostics[0].span), "| 2 | oops");
+        assert!(parse_in(ParseMode::Strict, source).is_err());
+    }
+
+    #[test]
+    fn unclosed_docstrings_point_at_their_opener() {
+        let source = r#"Feature: Generated
+  Scenario: First
+    Given a payload
+      """
+      {"id": 1}
+
+  Scenario: Second
+    Given another payload
+"#;
+        let parsed = parse_in(ParseMode::Permissive, source).unwrap();
+        let scenarios = &parsed.feature.scenarios;
+        assert_eq!(scenarios.len(), 2);
+        assert_eq!(
+            scenarios[0].steps[0].docstring.as_deref(),
+            Some("\n{\"id\": 1}\n")
+        );
+        let span = scenarios[0].steps[0].docstring_span.unwrap();
+        assert_eq!(parsed.text_of(span), "\"\"\"\n      {\"id\": 1}");
+        assert_eq!(scenarios[1].steps[0].value, "another payload");
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].code, "unclosed-docstring");
+        assert_eq!(parsed.diagnostics[0].position, (4, 7));
+
+        let err = parse_in(ParseMode::Strict, source).unwrap_err();
+        assert_eq!((err.location.line, err.location.column), (4, 7));
+        assert!(err.to_string().contains("docstring opened here"));
+    }
+
+    #[test]
+    fn unclosed_docstrings_recover_at_the_next_step_or_block() {
+        let source = r#"Feature: Forgetful
+  Scenario: First
+    Given a payload
+      """
+        Given this is prose
+        Scenario: quoted
+    When it is sent
+    Then it arrives
+
+  @next
+  Scenario: Second
+    Given another payload
+      """
+      closed
+      """
+"#;
+        let parsed = parse_in(ParseMode::Permissive, source).unwrap();
+        let scenarios = &parsed.feature.scenarios;
+        assert_eq!(scenarios.len(), 2);
+
+        let first = &scenarios[0];
+        assert_eq!(
+            first.steps[0].docstring.as_deref(),
+            Some("\n  Given this is prose\n  Scenario: quoted")
+        );
+        let values: Vec<_> = first.steps.iter().map(|s| &*s.value).collect();
+        assert_eq!(values, vec!["a payload", "it is sent", "it arrives"]);
+        assert_eq!(first.steps[1].position, (7, 5));
+
+        let second = &scenarios[1];
+        assert_eq!(second.name, "Second");
+        assert_eq!(second.tags, vec!["next"]);
+        assert_eq!(second.position, (11, 3));
+        assert_eq!(second.steps[0].position, (12, 5));
+        assert_eq!(second.steps[0].docstring.as_deref(), Some("\nclosed\n"));
+
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].position, (4, 7));
+        assert_eq!(
+            parsed.diagnostics[0].message,
+            "docstring was never closed and ends before line 7"
+        );
+
+        // Strict parsing pairs the delimiters as written, closing the first docstring with the
+        // second's opening delimiter.
+        assert!(parse_in(ParseMode::Strict, source).is_err());
+    }
+
+    #[test]
+    fn closed_docstrings_may_hold_dedented_step_and_block_lines() {
+        let source = r#"Feature: Quoting
+  Scenario: Nested feature
+    Given a file
+      """
+Feature: Nested
+  Scenario: Inner
+    Given y
+      """
+    Then it parses
+
+  Scenario: Advice
+    Given a note
+      """
+    When in doubt, quote
+      """
+"#;
+        let parsed = parse_in(ParseMode::Permissive, source).unwrap();
+        let scenarios = &parsed.feature.scenarios;
+        assert_eq!(scenarios.len(), 2);
+        assert_eq!(
+            scenarios[0].steps[0].docstring.as_deref(),
+            Some("\nFeature: Nested\nScenario: Inner\nGiven y\n")
+        );
+        assert_eq!(scenarios[0].steps[1].value, "it parses");
+        assert_eq!(
+            scenarios[1].steps[0].docstring.as_deref(),
+            Some("\nWhen in doubt, quote\n")
+        );
+        assert!(parsed.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn docstring_examples_are_an_error_at_the_docstring() {
+        let source = r#"Feature: Misplaced
+  Scenario Outline: Docstring examples
+    Given <x>
+
+    Examples:
+      """
+      | x |
+      | 1 |
+      """
+"#;
+
+        for mode in [ParseMode::Permissive, ParseMode::Strict].iter() {
+            let err = parse_in(*mode, source).unwrap_err();
+            assert_eq!((err.location.line, err.location.column), (6, 7));
+            assert!(err
+                .to_string()
+                .contains("examples table rather than a docstring"));
+        }
+    }
+
+    #[test]
+    fn recovered_mistakes_do_not_take_over_later_errors() {
+        let source = r#"Feature: Recovered
+  Scenario: Unclosed
+    Given a payload
+      """
+      never closed
+    When it is sent
+
+  Scenario: Broken
+    Given a step
+  Nonsense here
+"#;
+
+        let err = parse_in(ParseMode::Permissive, source).unwrap_err();
+        assert_ne!(err.location.line, 4);
+        assert!(!err.to_string().contains("docstring opened here"));
+
+        let err = parse_in(ParseMode::Strict, source).unwrap_err();
+        assert_eq!((err.location.line, err.location.column), (4, 7));
+    }
+
+    #[test]
+    fn rule_backgrounds_keep_their_place() {
+        let source = include_str!("../tests/fixtures/rule_backgrounds.feature");
+
+        let parsed = parse_in(ParseMode::Permissive, source).unwrap();
+        let feature = &parsed.feature;
+        let late = &feature.rules[1];
+        assert!(matches!(late.children[0], RuleChild::Scenario(_)));
+        assert!(matches!(late.children[1], RuleChild::Background(_)));
+        assert_eq!(late.background_steps()[0].value, "a guest");
+        assert_eq!(late.scenarios().count(), 2);
+
+        let out = feature.to_gherkin_string(&FormatOptions::default());
+        let browsing = out.find("Scenario: Browsing").unwrap();
+        let guest = out.find("Given a guest").unwrap();
+        let leaving = out.find("Scenario: Leaving").unwrap();
+        assert!(browsing < guest && guest < leaving);
+        assert_eq!(
+            Feature::parse(&out).unwrap().golden_digest(),
+            feature.golden_digest()
+        );
+
+        let leaving = late.scenarios().nth(1).unwrap();
+        assert_eq!(leaving.step_count(true, feature), 3);
+        assert_eq!(feature.total_step_count(), 3 + 3 + 3);
+
+        let mut inlined = feature.clone();
+        inlined.inline_background();
+        assert!(inlined.rules.iter().all(|r| r.background().is_none()));
+        let steps: Vec<_> = inlined.rules[1]
+            .scenarios()
+            .map(|s| s.steps.iter().map(|s| &*s.value).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                vec!["a shop", "a guest", "they browse"],
+                vec!["a shop", "a guest", "they leave"]
+            ]
+        );
+
+        let err = parse_in(ParseMode::Strict, source).unwrap_err();
+        assert_eq!((err.location.line, err.location.column), (17, 5));
+        assert!(err
+            .to_string()
+            .contains("background before the scenarios of the rule"));
+
+        let twice = "Feature: A\n  Rule: B\n    Background:\n      Given a\n    Background:\n      Given b\n";
+        let err = Feature::parse(twice).unwrap_err();
+        assert_eq!((err.location.line, err.location.column), (5, 5));
+        assert!(err.to_string().contains("at most one background in a rule"));
+    }
+
+    #[test]
+    fn inlined_background_steps_keep_no_positions() {
+        let mut feature = Feature::parse(
+            "Feature: A\n  Background:\n    Given users\n      | name |\n      | ann  |\n    And a note\n      \"\"\"\n      hi\n      \"\"\"\n  Scenario: B\n    When they log in\n",
+        )
+        .unwrap();
+        feature.inline_background();
+
+        let step = &feature.scenarios[0].steps[0];
+        let table = step.table.as_ref().unwrap();
+        assert_eq!((table.span, table.position), ((0, 0), (0, 0)));
+        assert_eq!(table.row_positions, vec![(0, 0); 2]);
+        assert_eq!(feature.scenarios[0].steps[1].docstring_span, Some((0, 0)));
+        assert_ne!(feature.scenarios[0].steps[2].position, (0, 0));
+    }
+
+    #[test]
+    fn numeric_looking_cells_are_kept_verbatim() {
+        let feature = Feature::parse(
+            "Feature: Numbers\n  Scenario Outline: A\n    Given <n>\n\n    Examples:\n      |  n   |\n      | 01   |\n      |  1.0 |\n      | +5   |\n      | 1e3  |\n",
+        )
+        .unwrap();
+        let table = feature.scenarios[0].examples[0].table.as_ref().unwrap();
+        assert_eq!(
+            table.rows,
+            vec![vec!["n"], vec!["01"], vec!["1.0"], vec!["+5"], vec!["1e3"]]
+        );
+        let values: Vec<_> = feature.scenarios[0]
+            .expand_examples()
+            .into_iter()
+            .map(|s| s.steps[0].value.clone())
+            .collect();
+        assert_eq!(values, vec!["01", "1.0", "+5", "1e3"]);
+    }
+
+    #[test]
+    fn table_rows_need_pipes_at_both_ends() {
+        let table = |source: &str| {
+            let source = format!(
+                "Feature: Pipes\n  Scenario: A\n    Given a table\n{}",
+                source
+            );
+            parse_in(ParseMode::Permissive, &source)
+                .map(|p| (p.feature.scenarios[0].steps[0].table.clone(), p.diagnostics))
+        };
+
+        let (rows, _) = table("      | a | b |\n      |1|2|\n").unwrap();
+        assert_eq!(rows.unwrap().rows, vec![vec!["a", "b"], vec!["1", "2"]]);
+
+        let (rows, _) = table("      | a | b ||\n").unwrap();
+        assert_eq!(rows.unwrap().rows, vec![vec!["a", "b", ""]]);
+
+        let (rows, diagnostics) = table("      | a | b |\n      1 | 2 |\n      | 3 | 4\n").unwrap();
+        assert_eq!(rows.unwrap().rows, vec![vec!["a", "b"]]);
+        let codes: Vec<_> = diagnostics.iter().map(|d| (d.code, d.position)).collect();
+        assert_eq!(
+            codes,
+            vec![("invalid-table-row", (5, 7)), ("invalid-table-row", (6, 7))]
+        );
+
+        assert!(table("      a | b |\n").is_err());
+        let source = "Feature: Pipes\n  Scenario: A\n    Given a table\n      | a |\n      b |\n";
+        assert!(parse_in(ParseMode::Strict, source).is_err());
+    }
+
+    #[test]
+    fn table_dimensions() {
+        let feature = Feature::parse(
+            "Feature: A\n  Scenario: B\n    Given c\n      | a | b | c |\n      | 1 | 2 | 3 |\n",
+        )
+        .unwrap();
+        let table = feature.scenarios[0].steps[0].table.as_ref().unwrap();
+
+        assert_eq!(table.dimensions(), (2, 3));
+        assert!(!table.is_empty());
+        assert!(Table::builder().rows(vec![]).build().is_empty());
+    }
+
+    #[test]
+    fn tab_delimited_tables() {
+        let piped =
+            "Feature: Export\n  Scenario: A\n    Given a table\n      | a | b |\n      | 1 |   |\n";
+        let tabbed =
+            "Feature: Export\n  Scenario: A\n    Given a table\n      \ta\tb\t\n      \t1\t\t\n";
+        let options = ParseOptions::default().with_table_delimiter('\t');
+
+        let piped = Feature::parse(piped).unwrap();
+        let parsed = Feature::parse_with_options(tabbed.to_string(), &options).unwrap();
+        let table = |f: &Feature| f.scenarios[0].steps[0].table.clone().unwrap();
+
+        assert_eq!(table(&parsed.feature).rows, table(&piped).rows);
+        assert_eq!(parsed.source, tabbed);
+        assert!(Feature::parse(tabbed).is_err());
+    }
+
+    #[test]
+    fn table_delimiters_are_only_read_where_tables_go() {
+        let source = r#"Feature: Commas
+  ,a description line,
+
+  Scenario Outline: A
+    Given a table
+      ,a,b,
+      # a comment
+      ,1,2,
+    And a docstring
+      """
+      ,not,a,row,
+      """
+
+    Examples:
+
+      ,x,
+      ,3,
+"#;
+        let options = ParseOptions::default().with_table_delimiter(',');
+        let feature = Feature::parse_with_options(source.to_string(), &options)
+            .unwrap()
+            .feature;
+        let scenario = &feature.scenarios[0];
+
+        assert_eq!(feature.description.as_deref(), Some(",a description line,"));
+        assert_eq!(
+            scenario.steps[0].table.as_ref().unwrap().rows,
+            vec![vec!["a", "b"], vec!["1", "2"]]
+        );
+        assert_eq!(
+            scenario.steps[1].docstring.as_deref(),
+            Some("\n,not,a,row,\n")
+        );
+        assert_eq!(
+            scenario.examples[0].table.as_ref().unwrap().rows,
+            vec![vec!["x"], vec!["3"]]
+        );
+    }
+
+    #[test]
+    fn parse_metrics_match_structure() {
+        let source = r"Feature: Metrics
+  Background:
+    Given a clean slate
+
+  Scenario Outline: Adding
+    Given <a> and <b>
+    Then the sum is <c>
+
+    Examples:
+      | a | b | c |
+      | 1 | 2 | 3 |
+
+  Rule: Tables
+    Scenario: A table
+      Given these users
+        | name  |
+        | alice |
+        | bob   |
+";
+        let options = ParseOptions::default().with_metrics(true);
+        let parsed = Feature::parse_with_options(source.to_string(), &options).unwrap();
+        let metrics = parsed.metrics.unwrap();
+
+        assert_eq!(metrics.bytes, source.len());
+        assert_eq!(metrics.scenarios, 2);
+        assert_eq!(metrics.steps, 4);
+        assert_eq!(metrics.table_cells, 9);
+        assert_eq!(metrics.errors, 0);
+        assert_eq!(metrics.warnings, 0);
+    }
+
+    #[test]
+    fn normalizes_crlf_line_endings() {
+        let source = "Feature: Windows\r\n  Lines end\r\n  in CRLF\r\n\r\n  Scenario: A\r\n    Given a payload\r\n      \"\"\"\r\n      one\r\n      two\r\n      \"\"\"\r\n";
+        let mut feature = Feature::parse(source).unwrap();
+        assert!(!format!("{:?}", feature).contains("\\r"));
+        assert_eq!(feature.line_ending, LineEnding::CrLf);
+
+        feature.scenarios[0].steps[0].docstring = Some("\r\none\rtwo\r\n".to_string());
+        feature.normalize_line_endings();
+        let normalized = feature.clone();
+        feature.normalize_line_endings();
+
+        assert_eq!(feature, normalized);
+        assert!(!format!("{:?}", feature).contains("\\r"));
+        assert_eq!(
+            feature.scenarios[0].steps[0].docstring.as_deref(),
+            Some("\none\ntwo\n")
+        );
+    }
+
+    #[test]
+    fn mixed_line_endings_parse_like_lf() {
+        let mixed = "Feature: Mixed\r\n  Some prose\r  more prose\n\r\n  Scenario: A\r    Given a payload\r\n      \"\"\"\r      one\n      two\r\n      \"\"\"\r    Then a table\n      | a |\r\n      | 1 |\r";
+        let lf = mixed.replace("\r\n", "\n").replace('\r', "\n");
+
+        let mixed = Feature::parse_with_source(mixed.to_string()).unwrap();
+        let lf = Feature::parse(&lf).unwrap();
+        assert_eq!(mixed.feature.golden_digest(), lf.golden_digest());
+        let positions = |f: &Feature| f.all_steps().map(|s| s.position).collect::<Vec<_>>();
+        assert_eq!(positions(&mixed.feature), positions(&lf));
+        assert_eq!(positions(&lf), vec![(6, 5), (11, 5)]);
+        assert!(!format!("{:?}", mixed.feature).contains("\\r"));
+        assert!(mixed.feature.trailing_newline);
+
+        assert_eq!(mixed.feature.line_ending, LineEnding::CrLf);
+        assert_eq!(lf.line_ending, LineEnding::Lf);
+        let out = mixed.feature.to_gherkin_string(&FormatOptions::default());
+        assert!(out.starts_with("Feature: Mixed\r\n  Some prose\r\n"));
+        assert_eq!(out.matches('\n').count(), out.matches("\r\n").count());
+
+        let old_mac = Feature::parse("Feature: Old\r  Scenario: A\r    Given a step\r").unwrap();
+        assert_eq!(old_mac.line_ending, LineEnding::Cr);
+        assert_eq!(
+            old_mac.to_gherkin_string(&FormatOptions::default()),
+            "Feature: Old\r\r  Scenario: A\r    Given a step\r"
+        );
+    }
+
+    #[test]
+    fn feature_title_on_next_line() {
+        let source =
+            "Feature:\n  Generated title\n  A description\n\n  Scenario: A\n    Given a step\n";
+
+        let parsed = parse_in(ParseMode::Permissive, source).unwrap();
+        assert_eq!(parsed.feature.name, "");
+        assert_eq!(
+            parsed.feature.description.as_deref(),
+            Some("Generated title\nA description")
+        );
+
+        let options = ParseOptions::default().with_title_on_next_line(true);
+        let parsed = Feature::parse_with_options(source.to_string(), &options).unwrap();
+        assert_eq!(parsed.feature.name, "Generated title");
+        assert_eq!(parsed.feature.description.as_deref(), Some("A description"));
+        assert_eq!(parsed.feature.scenarios.len(), 1);
+
+        let untitled = "Feature:\n  Scenario: A\n    Given a step\n";
+        let parsed = Feature::parse_with_options(untitled.to_string(), &options).unwrap();
+        assert_eq!(parsed.feature.name, "");
+        assert_eq!(parsed.feature.scenarios[0].name, "A");
+    }
+
+    #[test]
+    fn equality_includes_layout() {
+        let source = "Feature: Layout\n  Scenario: A\n    Given a step\n";
+        let reindented = "Feature: Layout\nScenario: A\n  Given a step\n";
+        let feature = Feature::parse(source).unwrap();
+
+        assert_eq!(feature, Feature::parse(source).unwrap());
+        assert_ne!(feature, Feature::parse(reindented).unwrap());
+        assert_eq!(
+            feature.golden_digest(),
+            Feature::parse(reindented).unwrap().golden_digest()
+        );
+    }
+
+    #[test]
+    fn multi_line_descriptions_are_kept() {
+        let feature = Feature::parse(
+            "Feature: Prose\n  In order to read the docs\n  As a reader\n    I want the description kept\n\n  Scenario: A\n    Given a step\n",
+        )
+        .unwrap();
+        assert_eq!(
+            feature.description.as_deref(),
+            Some("In order to read the docs\nAs a reader\nI want the description kept")
+        );
+
+        let feature =
+            Feature::parse("Feature: Terse\n\n  Scenario: A\n    Given a step\n").unwrap();
+        assert_eq!(feature.description, None);
+    }
+
+    #[test]
+    fn backgrounds_may_have_descriptions() {
+        let source = r"Feature: Accounts
+  Background:
+    Every scenario starts with
+    a signed in customer
+
+    Given a customer
+    And they are signed in
+
+  Scenario: A
+    Given a step
+";
+        let feature = Feature::parse(source).unwrap();
+        let background = feature.background.as_ref().unwrap();
+
+        assert_eq!(
+            background.description.as_deref(),
+            Some("Every scenario starts with\na signed in customer")
+        );
+        assert_eq!(
+            background
+                .steps
+                .iter()
+                .map(|s| s.value.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a customer", "they are signed in"]
+        );
+        assert_eq!(feature.scenarios.len(), 1);
+        assert_eq!(
+            Feature::parse(feature.to_gherkin_string(&FormatOptions::default()))
+                .unwrap()
+                .golden_digest(),
+            feature.golden_digest()
+        );
+    }
+
+    #[test]
+    fn descriptions_can_be_skipped() {
+        let source = "Feature: Prose\n  Some prose\n  over two lines\n\n  Rule: Quiet\n    More prose\n\n    Scenario: A\n      Given a step\n";
+        let options = ParseOptions::default().with_capture_descriptions(false);
+        let parsed = Feature::parse_with_options(source.to_string(), &options).unwrap();
+
+        assert_eq!(parsed.feature.description, None);
+        assert_eq!(parsed.feature.rules[0].description, None);
+        assert_eq!(
+            parsed.feature.rules[0]
+                .scenarios()
+                .next()
+                .unwrap()
+                .steps
+                .len(),
+            1
+        );
+        assert!(Feature::parse(source).unwrap().description.is_some());
+    }
+
+    #[test]
+    fn counts_scenarios_by_tag() {
+        let feature = Feature::parse(
+            r"@billing
+Feature: Invoices
+
+  @smoke
+  Scenario: Paying
+    Given an invoice
+
+  Rule: Refunds
+    @smoke @slow
+    Scenario: Refunding
+      Given a refund
+
+    Scenario: Cancelling
+      Given a cancellation
+",
+        )
+        .unwrap();
+
+        let counts: Vec<_> = feature.count_by_tag().into_iter().collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("billing".to_string(), 3),
+                ("slow".to_string(), 1),
+                ("smoke".to_string(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn tag_histogram_sums_features() {
+        let features = [
+            Feature::parse("Feature: A\n  @smoke\n  Scenario: One\n    Given a step\n").unwrap(),
+            Feature::parse(
+                "@slow\nFeature: B\n  @smoke\n  Scenario: Two\n    Given a step\n  Scenario: Three\n    Given a step\n",
+            )
+            .unwrap(),
+        ];
+
+        let histogram: Vec<_> = Feature::tag_histogram(&features).into_iter().collect();
+        assert_eq!(
+            histogram,
+            vec![("slow".to_string(), 2), ("smoke".to_string(), 2)]
+        );
+        assert!(Feature::tag_histogram(&[]).is_empty());
+    }
+
+    #[test]
+    fn step_counts_and_costs() {
+        let feature = Feature::parse(
+            r"Feature: Costs
+  Background:
+    Given a clean slate
+    And a user
+
+  Scenario: Plain
+    Given a step
+    When I wait 5 seconds
+    Then it is done
+
+  Scenario Outline: Waiting
+    When I wait <n> seconds
+    Then it is done
+
+    Examples:
+      | n |
+      | 1 |
+      | 2 |
+
+    Examples:
+      | n |
+      | 3 |
+
+  Scenario Outline: Never runs
+    When I wait <n> seconds
+
+    Examples:
+      | n |
+
+  Rule: Ruled
+    Scenario: Short
+      Then it is done
+",
+        )
+        .unwrap();
+        let plain = &feature.scenarios[0];
+
+        assert_eq!(plain.step_count(false, &feature), 3);
+        assert_eq!(plain.step_count(true, &feature), 5);
+        assert_eq!(feature.scenarios[1].step_count(true, &feature), 4);
+        // 5 for Plain, 4 for each of Waiting's 3 rows, none for Never runs and 3 for Short.
+        assert_eq!(feature.total_step_count(), 20);
+        assert_eq!(feature.weighted_cost(|_| 1.0), 20.0);
+
+        let seconds = |step: &Step| {
+            step.value
+                .split(' ')
+                .find_map(|w| w.parse::<f64>().ok())
+                .unwrap_or(0.0)
+        };
+        assert_eq!(feature.weighted_cost(seconds), 5.0 + 1.0 + 2.0 + 3.0);
+    }
+
+    #[test]
+    fn strips_matching_tags() {
+        let mut feature = Feature::parse(
+            r"@wip @billing
+Feature: Invoices
+
+  @wip
+  Scenario: Paying
+    Given an invoice
+
+  @wip
+  Rule: Refunds
+    @smoke @wip
+    Scenario Outline: Refunding
+      Given <n> refunds
+
+      @wip
+      Examples:
+        | n |
+        | 1 |
+",
+        )
+        .unwrap();
+        feature.strip_tags(|t| t == "wip");
+
+        assert!(!feature.tag_set().contains("wip"));
+        assert_eq!(feature.tags, vec!["billing"]);
+        assert_eq!(feature.tag_tokens.len(), 1);
+        assert!(feature.scenarios[0].tags.is_empty());
+        assert!(feature.rules[0].tags.is_empty());
+        let refunding = feature.rules[0].scenarios().next().unwrap();
+        assert_eq!(refunding.tags, vec!["smoke"]);
+        assert_eq!(refunding.tag_tokens[0].name, "smoke");
+        assert!(refunding.examples[0].tags.is_empty());
+    }
+
+    #[test]
+    fn finds_scenario_indices() {
+        let feature = Feature::parse(
+            r"Feature: Indices
+  Scenario: A
+    Given a step
+
+  Scenario: B
+    Given a step
+
+  Rule: First
+    Scenario: A
+      Given a step
+",
+        )
+        .unwrap();
+        let indices: Vec<_> = feature
+            .ordered_scenarios(|_| None)
+            .iter()
+            .map(|s| feature.scenario_index(s))
+            .collect();
+
+        assert_eq!(indices, vec![Some(0), Some(1), Some(2)]);
+        assert_eq!(feature.scenario_index(&feature.scenarios[1]), Some(1));
+        let cloned = feature.rules[0].scenarios().next().unwrap().clone();
+        assert_eq!(feature.scenario_index(&cloned), Some(2));
+
+        let mut moved = feature.scenarios[1].clone();
+        moved.position = (1, 1);
+        assert_eq!(feature.scenario_index(&moved), None);
+    }
+
+    #[test]
+    fn inlines_and_concatenates_backgrounds() {
+        let login = Feature::parse(
+            r"@web
+Feature: Login
+  Background:
+    Given a user
+
+  Scenario Outline: Logging in
+    When they log in as <role>
+
+    Examples:
+      | role  |
+      | admin |
+
+  Rule: Lockout
+    Scenario: Locked
+      When they fail 3 times
+",
+        )
+        .unwrap();
+        let logout = Feature::parse(
+            "Feature: Logout\n\n  Background:\n\n    Given a user\n\n  Scenario: Logging out\n    When they log out\n",
+        )
+        .unwrap();
+        let other = Feature::parse(
+            "Feature: Other\n  Background:\n    Given a guest\n  Scenario: Browsing\n    When they browse\n",
+        )
+        .unwrap();
+
+        let mut inlined = login.clone();
+        inlined.inline_background();
+        assert_eq!(inlined.background, None);
+        let outline = &inlined.scenarios[0];
+        assert_eq!(outline.steps[0].value, "a user");
+        assert!(outline.steps[0].from_background);
+        assert_eq!(outline.steps[0].position, (0, 0));
+        assert_eq!(outline.steps[1].value, "they log in as <role>");
+        assert_eq!(inlined.rules[0].scenarios().next().unwrap().steps.len(), 2);
+
+        let shared = concat(vec![login.clone(), logout.clone()], "Accounts");
+        assert_eq!(shared.name, "Accounts");
+        assert_eq!(shared.background.as_ref().unwrap().steps.len(), 1);
+        assert_eq!(
+            shared.scenario_names(),
+            vec!["Logging in", "Logging out", "Locked"]
+        );
+        assert_eq!(shared.scenarios[0].tags, vec!["web"]);
+        assert_eq!(shared.rules[0].tags, vec!["web"]);
+        assert!(shared.scenarios[1].tags.is_empty());
+
+        let combined = concat(vec![login, logout, other], "Everything");
+        assert_eq!(combined.background, None);
+        let first_steps: Vec<_> = combined
+            .all_scenarios()
+            .map(|s| &*s.steps[0].value)
+            .collect();
+        assert_eq!(first_steps, vec!["a user", "a user", "a guest", "a user"]);
+        let out = combined.to_gherkin_string(&FormatOptions::default());
+        assert!(out.find("Logging out").unwrap() < out.find("Rule: Lockout").unwrap());
+    }
+
+    #[test]
+    fn maps_every_step() {
+        let feature = Feature::parse(
+            r"Feature: Shouting
+  Background:
+    Given a room
+
+  Scenario: Talking
+    When I talk
+
+  Scenario Outline: Singing
+    When I sing <song>
+
+    Examples:
+      | song |
+      | la   |
+
+  Rule: Quiet
+    Scenario: Whispering
+      Then nobody hears
+",
+        )
+        .unwrap();
+
+        let shouted = feature.map_steps(|step| Step {
+            value: step.value.to_uppercase(),
+            ..step.clone()
+        });
+        let values: Vec<_> = shouted.all_steps().map(|s| &*s.value).collect();
+        assert_eq!(
+            values,
+            vec!["A ROOM", "I TALK", "I SING <SONG>", "NOBODY HEARS"]
+        );
+        let positions: Vec<_> = shouted.all_steps().map(|s| s.position).collect();
+        let original: Vec<_> = feature.all_steps().map(|s| s.position).collect();
+        assert_eq!(positions, original);
+        assert_eq!(feature.scenarios[0].steps[0].value, "I talk");
+    }
+
+    #[test]
+    fn rerun_entries_resolve_to_scenarios_and_rows() {
+        let mut feature = Feature::parse(
+            r"Feature: Rerun
+  Scenario: Plain
+    Given a step
+
+  Scenario Outline: Rows
+    Given <n>
+
+    Examples:
+      | n |
+      | 1 |
+      | 2 |
+",
+        )
+        .unwrap();
+        feature.path = Some(PathBuf::from("features/foo.feature"));
+
+        let plain = &feature.scenarios[0];
+        assert_eq!(rerun_entry(&feature, plain), "features/foo.feature:2");
+        let rows = feature.scenarios[1].expand_examples();
+        let entries: Vec<_> = rows.iter().map(|s| rerun_entry(&feature, s)).collect();
+        assert_eq!(
+            entries,
+            vec!["features/foo.feature:10", "features/foo.feature:11"]
+        );
+
+        assert_eq!(feature.scenario_or_row_at_line(2).as_ref(), Some(plain));
+        assert_eq!(feature.scenario_or_row_at_line(11), Some(rows[1].clone()));
+        assert_eq!(
+            feature.scenario_or_row_at_line(5).unwrap().examples.len(),
+            1
+        );
+        assert_eq!(feature.scenario_or_row_at_line(9), None);
+    }
+
+    #[test]
+    fn background_steps_may_be_empty() {
+        let with = Feature::parse("Feature: A\n  Background:\n    Given a\n    And b\n").unwrap();
+        let values: Vec<_> = with.background_steps().iter().map(|s| &*s.value).collect();
+        assert_eq!(values, vec!["a", "b"]);
+
+        let without = Feature::parse("Feature: A\n  Scenario: B\n    Given c\n").unwrap();
+        assert!(without.background_steps().is_empty());
+    }
+
+    #[test]
+    fn large_examples_expand_lazily_or_within_a_limit() {
+        let mut source = String::from(
+            "Feature: Large\n  Scenario Outline: Many\n    Given row <n>\n\n    Examples:\n      | n |\n",
+        );
+        for n in 0..5000 {
+            source.push_str(&format!("      | {} |\n", n));
+        }
+        let feature = Feature::parse(&source).unwrap();
+        let outline = &feature.scenarios[0];
+
+        let err = outline.try_expand_examples(1000).unwrap_err();
+        assert_eq!(err.scenario, "Many");
+        assert_eq!(err.position, (2, 3));
+        assert_eq!(err.count, 5000);
+        assert_eq!(outline.try_expand_examples(5000).unwrap().len(), 5000);
+
+        let mut lazy = outline.iter_expansions().skip(4998);
+        assert_eq!(lazy.next().unwrap().steps[0].value, "row 4998");
+        assert_eq!(lazy.next().unwrap().example_row, Some((5006, 7)));
+        assert_eq!(lazy.next(), None);
+
+        let plain = Scenario::builder().name("One".into()).steps(vec![]).build();
+        assert_eq!(
+            plain.iter_expansions().collect::<Vec<_>>(),
+            vec![plain.clone()]
+        );
+        assert_eq!(plain.try_expand_examples(0).unwrap_err().count, 1);
+    }
+
+    #[test]
+    fn example_row_comments() {
+        let source = r"Feature: Browsers
+  Scenario Outline: Loading
+    Given <browser> at <version>
+
+    Examples:
+      | browser | version |
+      # Evergreen
+      | chrome  | 114     | # flaky on CI
+      | firefox | 115     |
+      # Legacy
+      # (still supported)
+      | ie      | 11      |#slow
+";
+
+        for mode in [ParseMode::Permissive, ParseMode::Strict].iter() {
+            let parsed = parse_in(*mode, source).unwrap();
+            assert!(parsed.diagnostics.is_empty());
+            let examples = &parsed.feature.scenarios[0].examples[0];
+            let table = examples.table.as_ref().unwrap();
+
+            assert_eq!(table.rows.len(), 4);
+            assert_eq!(table.rows[1], vec!["chrome", "114"]);
+            assert_eq!(
+                table.row_comments,
+                vec![None, Some("flaky on CI".into()), None, Some("slow".into())]
+            );
+            assert_eq!(table.row_leading_comments[1], vec!["Evergreen"]);
+            assert_eq!(
+                table.row_leading_comments[3],
+                vec!["Legacy", "(still supported)"]
+            );
+            assert_eq!(examples.row_position(2), Some((12, 7)));
+        }
+
+        let feature = Feature::parse(source).unwrap();
+        let out = feature.to_gherkin_string(&FormatOptions::default());
+        assert!(out.contains("      # Evergreen\n      | chrome  | 114     | # flaky on CI\n"));
+        assert!(out.contains("      # (still supported)\n      | ie      | 11      | # slow\n"));
+        assert_eq!(
+            Feature::parse(&out).unwrap().golden_digest(),
+            feature.golden_digest()
+        );
+
+        let ie = feature.scenarios[0].examples[0].filter_rows(|r| r.get("browser") == Some("ie"));
+        let table = ie.table.unwrap();
+        assert_eq!(table.row_comments, vec![None, Some("slow".into())]);
+    }
+
+    #[test]
+    fn strips_positions_and_comments() {
+        let feature = Feature::parse(
+            r"@web
+Feature: Stripping
+  Background:
+    Given a browser
+
+  Rule: Outlines
+    @slow
+    Scenario Outline: Loading <page>
+      Given a table
+        | a |
+      When I load <page>
+
+      @smoke
+      Examples:
+        | page |
+        # Home
+        | home | # first
+",
+        )
+        .unwrap();
+
+        let stripped = feature.without_positions();
+        assert_eq!(stripped.golden_digest(), feature.golden_digest());
+        assert_ne!(stripped, feature);
+
+        let rule = &stripped.rules[0];
+        let scenario = rule.scenarios().next().unwrap();
+        let examples = &scenario.examples[0];
+        let mut positions = vec![
+            stripped.span,
+            stripped.position,
+            stripped.tag_tokens[0].position,
+            stripped.background.as_ref().unwrap().position,
+            stripped.background.as_ref().unwrap().steps[0].span,
+            rule.span,
+            rule.position,
+            scenario.span,
+            scenario.position,
+            scenario.tag_tokens[0].span,
+            scenario.steps[0].position,
+            scenario.steps[0].table.as_ref().unwrap().position,
+            examples.span,
+            examples.position,
+            examples.tag_tokens[0].position,
+            examples.table.as_ref().unwrap().span,
+        ];
+        positions.extend(examples.table.as_ref().unwrap().row_positions.iter());
+        positions.extend(scenario.expand_examples()[0].example_row);
+        assert_eq!(positions.len(), 19);
+        assert!(positions.iter().all(|p| *p == (0, 0)), "{:?}", positions);
+
+        let mut uncommented = feature.clone();
+        uncommented.strip_comments();
+        let table = uncommented.rules[0].scenarios().next().unwrap().examples[0]
+            .table
+            .as_ref()
+            .unwrap();
+        assert_eq!(table.row_comments, vec![None, None]);
+        assert_eq!(
+            table.row_leading_comments,
+            vec![Vec::<String>::new(), vec![]]
+        );
+        assert!(!uncommented
+            .to_gherkin_string(&FormatOptions::default())
+            .contains('#'));
+    }
+
+    #[test]
+    fn sorts_scenarios() {
+        let mut feature = Feature::parse(
+            r"Feature: Sorting
+  Scenario: Charlie
+    Given a step
+
+  Scenario: Alpha
+    Given a step
+
+  Scenario: Bravo
+    Given a step
+
+  Rule: Nested
+    Scenario: Zulu
+      Given a step
+
+    Scenario: Yankee
+      Given a step
+",
+        )
+        .unwrap();
+        let names = |f: &Feature| f.scenario_names().join(" ");
+
+        feature.sort_scenarios_by_name();
+        assert_eq!(names(&feature), "Alpha Bravo Charlie Yankee Zulu");
+
+        feature.sort_scenarios_by_position();
+        assert_eq!(names(&feature), "Charlie Alpha Bravo Zulu Yankee");
+    }
+
+    #[test]
+    fn filters_example_rows() {
+        let feature = Feature::parse(
+            r"Feature: Browsers
+  Scenario Outline: Loading
+    Given <browser> at <width>
+
+    Examples:
+      | browser | width |
+      | firefox | 800   |
+      | chrome  | 800   |
+      | firefox | 1200  |
+
+    Examples:
+      | browser | width |
+      | safari  | 800   |
+",
+        )
+        .unwrap();
+        let outline = &feature.scenarios[0];
+
+        let third = outline.examples[0].filter_rows(|row| row.line() == 9);
+        assert_eq!(third.rows().count(), 1);
+        assert_eq!(third.row_position(0), Some((9, 7)));
+
+        let firefox = outline.filter_examples_by("browser", "firefox").unwrap();
+        let steps: Vec<_> = firefox
+            .expand_examples()
+            .into_iter()
+            .map(|s| s.steps[0].value.clone())
+            .collect();
+        assert_eq!(steps, vec!["firefox at 800", "firefox at 1200"]);
+        assert_eq!(firefox.examples[0].row_position(1), Some((9, 7)));
+        assert_eq!(firefox.examples[1].rows().count(), 0);
+
+        assert_eq!(
+            outline.filter_examples_by("os", "linux"),
+            Err(CellParseError::MissingColumn {
+                column: "os".to_string(),
+                line: 6
+            })
+        );
+    }
+
+    #[test]
+    fn filtering_examples_without_a_header_is_an_error() {
+        let mut outline = Feature::parse(
+            "Feature: Empty\n  Scenario Outline: A\n    Given <a>\n\n    Examples:\n      | a |\n",
+        )
+        .unwrap()
+        .scenarios
+        .remove(0);
+        let table = outline.examples[0].table.as_mut().unwrap();
+        table.rows.clear();
+        table.row_positions.clear();
+
+        assert_eq!(
+            outline.filter_examples_by("a", "1"),
+            Err(CellParseError::MissingColumn {
+                column: "a".to_string(),
+                line: 6
+            })
+        );
+    }
+
+    #[test]
+    fn steps_may_start_with_then() {
+        let feature = Feature::parse(
+            "Feature: Order\n  Scenario: Backwards\n    Then a result\n    And another\n    When an action\n",
+        )
+        .unwrap();
+        let types: Vec<_> = feature.scenarios[0].steps.iter().map(|s| s.ty).collect();
+        assert_eq!(types, vec![StepType::Then, StepType::Then, StepType::When]);
+    }
+
+    #[test]
+    fn tag_removal_edits() {
+        let source = "@first @wip @last\nFeature: Tags\n\n  @wip\n  Scenario: Only tag\n";
+        let feature = Feature::parse(source).unwrap();
+        let tags = &feature.tag_tokens;
+        let remove = |tag: &Tag| tag.removal_edit(source).apply(source);
+
+        assert_eq!(tags[0].position, (1, 1));
+        assert_eq!(&source[tags[1].span.0..tags[1].span.1], "@wip");
+        assert_eq!(
+            remove(&tags[0]),
+            "@wip @last\nFeature: Tags\n\n  @wip\n  Scenario: Only tag\n"
+        );
+        assert_eq!(
+            remove(&tags[1]),
+            "@first @last\nFeature: Tags\n\n  @wip\n  Scenario: Only tag\n"
+        );
+        assert_eq!(
+            remove(&tags[2]),
+            "@first @wip\nFeature: Tags\n\n  @wip\n  Scenario: Only tag\n"
+        );
+        assert_eq!(
+            remove(&feature.scenarios[0].tag_tokens[0]),
+            "@first @wip @last\nFeature: Tags\n\n  Scenario: Only tag\n"
+        );
+    }
+
+    #[test]
+    fn rule_description_and_tag_inheritance() {
+        let feature = Feature::parse(
+            r"@billing
+Feature: Invoices
+
+  @refunds
+  Rule: Refunds need approval
+    Refunds above the limit must be
+    approved by a manager.
+
+    @manager
+    Scenario: Large refund
+      Given a large refund
+",
+        )
+        .unwrap();
+        let rule = &feature.rules[0];
+        assert_eq!(rule.tags, vec!["refunds"]);
+        assert_eq!(
+            rule.description.as_deref(),
+            Some("Refunds above the limit must be\napproved by a manager.")
+        );
+        assert_eq!(
+            feature.effective_tags(rule.scenarios().next().unwrap()),
+            vec!["billing", "refunds", "manager"]
+        );
+    }
+
+    #[test]
+    fn scenarios_know_their_parent() {
+        let feature = Feature::parse(
+            "Feature: A\n  Scenario: B\n    Given a\n\n  Rule: C\n    Scenario: D\n      Given d\n",
+        )
+        .unwrap();
+        let rule = &feature.rules[0];
+
+        assert!(matches!(
+            feature.parent_of(&feature.scenarios[0]),
+            Some(ScenarioParent::Feature(f)) if std::ptr::eq(f, &feature)
+        ));
+        assert!(matches!(
+            feature.parent_of(rule.scenarios().next().unwrap()),
+            Some(ScenarioParent::Rule(r)) if r.name == "C"
+        ));
+        assert_eq!(feature.parent_of(&feature.scenarios[0].clone()), None);
+    }
+
+    #[test]
+    fn children_in_document_order() {
+        let feature = Feature::parse(
+            r"Feature: Ordering
+  Background:
+    Given a setup
+
+  Scenario: First
+    Given a step
+
+  Rule: Grouped
+    Scenario: Second
+      Given a step
+
+  Rule: Also grouped
+    Scenario: Third
+      Given a step
+",
+        )
+        .unwrap();
+        let order: Vec<_> = feature
+            .children()
+            .map(|c| match c {
+                FeatureChild::Background(_) => "Background".to_string(),
+                FeatureChild::Rule(r) => r.name.clone(),
+                FeatureChild::Scenario(s) => s.name.clone(),
+            })
+            .collect();
+        assert_eq!(
+            order,
+            vec!["Background", "First", "Grouped", "Also grouped"]
+        );
+    }
+
+    #[test]
+    fn scenarios_after_a_rule_belong_to_it() {
+        let feature = Feature::parse(
+            r"Feature: Interleaving
+  Scenario: Before
+    Given a step
+
+  Rule: Grouped
+    Scenario: Inside
+      Given a step
+
+  Scenario: After
+    Given a step
+",
+        )
+        .unwrap();
+        let order: Vec<_> = feature
+            .children()
+            .map(|c| match c {
+                FeatureChild::Background(_) => "Background".to_string(),
+                FeatureChild::Rule(r) => r.name.clone(),
+                FeatureChild::Scenario(s) => s.name.clone(),
+            })
+            .collect();
+        assert_eq!(order, vec!["Before", "Grouped"]);
+
+        let grouped: Vec<_> = feature.rules[0].scenarios().map(|s| &*s.name).collect();
+        assert_eq!(grouped, vec!["Inside", "After"]);
+    }
+
+    #[test]
+    fn scenarios_ordered_by_tag_number() {
+        let feature = Feature::parse(
+            r"Feature: Ordering
+  @order-20
+  Scenario: Second
+    Given a step
+
+  Scenario: Unordered
+    Given a step
+
+  Rule: Grouped
+    @order-10
+    Scenario: First
+      Given a step
+
+    @order-20 @slow
+    Scenario: Also second
+      Given a step
+
+    @order-x
+    Scenario: Also unordered
+      Given a step
+",
+        )
+        .unwrap();
+        let names: Vec<_> = feature
+            .ordered_scenarios(|s| s.tag_number("order-"))
+            .iter()
+            .map(|s| &*s.name)
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "First",
+                "Second",
+                "Also second",
+                "Unordered",
+                "Also unordered"
+            ]
+        );
+        assert_eq!(feature.scenarios[0].name, "Second");
+    }
+
+    #[test]
+    fn merging_backgrounds() {
+        let first = Feature::parse("Feature: A\n  Background:\n    Given one\n").unwrap();
+        let second = Feature::parse("Feature: B\n  Background:\n    Given two\n").unwrap();
+        let bare = Feature::parse("Feature: C\n").unwrap();
+
+        let mut merged = first.clone();
+        merged
+            .merge_backgrounds(&second, BackgroundMerge::Concatenate)
+            .unwrap();
+        let steps = &merged.background.as_ref().unwrap().steps;
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1].value, "two");
+
+        let mut merged = first.clone();
+        assert_eq!(
+            merged.merge_backgrounds(&second, BackgroundMerge::default()),
+            Err(BackgroundConflict)
+        );
+        assert_eq!(merged, first);
+
+        let mut merged = bare.clone();
+        merged
+            .merge_backgrounds(&second, BackgroundMerge::Error)
+            .unwrap();
+        assert_eq!(merged.background, second.background);
+    }
+
+    #[test]
+    fn examples_tags_apply_to_their_rows() {
+        let feature = Feature::parse(
+            r"@web
+Feature: Examples tags
+  Scenario Outline: Eating
+    Given <n> cucumbers
+
+    @smoke
+    Examples:
+      | n |
+      | 1 |
+
+    @slow
+    Examples:
+      | n |
+      | 2 |
+      | 3 |
+
+  Scenario: Untagged
+    Given a step
+",
+        )
+        .unwrap();
+
+        let smoke = feature.filter_by_expression(&"@smoke".parse().unwrap());
+        assert_eq!(smoke.scenarios.len(), 1);
+        assert_eq!(smoke.scenarios[0].examples.len(), 1);
+        let expanded = smoke.scenarios[0].expand_examples();
+        assert_eq!(expanded.len(), 1);
+        assert_eq!(expanded[0].steps[0].value, "1 cucumbers");
+        assert_eq!(feature.effective_tags(&expanded[0]), vec!["web", "smoke"]);
+
+        let slow = feature.filter_by_expression(&"@slow and @web".parse().unwrap());
+        let expanded = slow.scenarios[0].expand_examples();
+        assert_eq!(slow.scenarios.len(), 1);
+        assert_eq!(expanded.len(), 2);
+        assert!(expanded.iter().all(|s| s.tags == vec!["slow"]));
+
+        let not_slow = feature.filter_by_expression(&"not @slow".parse().unwrap());
+        assert_eq!(not_slow.scenario_names(), vec!["Eating", "Untagged"]);
+        assert_eq!(not_slow.scenarios[0].example_count(), 1);
+    }
+
+    #[test]
+    fn step_frequency_counts_repeated_steps() {
+        let feature = Feature::parse(
+            r"Feature: Carts
+Background:
+  Given a customer
+Scenario: Adding
+  When an item is added
+Scenario: Removing
+  When an item is added
+  And an item is removed
+",
+        )
+        .unwrap();
+        let frequency = feature.step_frequency();
+        assert_eq!(frequency["an item is added"], 2);
+        assert_eq!(frequency["an item is removed"], 1);
+        assert_eq!(frequency["a customer"], 1);
+    }
+
+    #[test]
+    fn output_is_deterministic() {
+        let a = Feature::parse(TAGGED).unwrap();
+        let b = Feature::parse(TAGGED).unwrap();
+        assert_eq!(format!("{:?}", a.tag_set()), format!("{:?}", b.tag_set()));
+        assert_eq!(a.stable_hash(), b.stable_hash());
+
+        let mut renamed = a.clone();
+        renamed.name = "Receipts".to_string();
+        assert_ne!(a.stable_hash(), renamed.stable_hash());
+
+        let mut moved = a.clone();
+        moved.path = Some(PathBuf::from("elsewhere.feature"));
+        assert_eq!(a.stable_hash(), moved.stable_hash());
+    }
+
+    #[test]
+    fn sub_steps_are_included_in_step_walks() {
+        let feature = Feature::parse_with_options(
+            "Feature: Checklists
+  Scenario Outline: Packing
+    When I pack
+      * a <item>
+  Examples:
+    | item       |
+    | toothbrush |
+"
+            .to_string(),
+            &ParseOptions::default().with_sub_steps(true),
+        )
+        .unwrap()
+        .feature;
+
+        let values: Vec<_> = feature.all_steps().map(|s| &*s.value).collect();
+        assert_eq!(values, vec!["I pack", "a <item>"]);
+
+        let stripped = feature.without_positions();
+        assert_eq!(stripped.all_steps().nth(1).unwrap().position, (0, 0));
+
+        let shouted = feature.map_steps(|s| Step {
+            value: s.value.to_uppercase(),
+            ..s.clone()
+        });
+        let values: Vec<_> = shouted.all_steps().map(|s| &*s.value).collect();
+        assert_eq!(values, vec!["I PACK", "A <ITEM>"]);
+
+        let expanded = feature.scenarios[0].expand_examples();
+        assert_eq!(expanded[0].steps[0].sub_steps[0].value, "a toothbrush");
     }
 }
@@ -0,0 +1,366 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::{Feature, Rule, Scenario};
+
+/// A scenario in a [`LinkGraph`]
+///
+/// Within the graph, a node is referred to by its index in [`LinkGraph::nodes`]. Across runs,
+/// `path` and `scenario_id` together name the scenario, as long as the features have distinct
+/// paths.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LinkNode<'a> {
+    /// The path of the feature containing the scenario, if known.
+    pub path: Option<&'a Path>,
+    /// The rule containing the scenario, if any.
+    pub rule: Option<&'a Rule>,
+    /// The scenario.
+    pub scenario: &'a Scenario,
+    /// The [`scenario_id`](Feature::scenario_id) of the scenario, which tells apart scenarios
+    /// of the same name in different rules of its feature.
+    pub scenario_id: u64,
+    /// The ids the scenario declares with id tags, without the prefix.
+    pub ids: Vec<String>,
+}
+
+/// A reference from one scenario to another, by the index of each in [`LinkGraph::nodes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Link {
+    /// The scenario with the reference tag.
+    pub from: usize,
+    /// The scenario declaring the id it names.
+    pub to: usize,
+}
+
+/// A reference tag naming an id that no scenario declares
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DanglingLink<'a> {
+    /// The index in [`LinkGraph::nodes`] of the scenario with the reference tag.
+    pub from: usize,
+    /// The id named, without the prefix.
+    pub id: String,
+    /// The path of the feature containing the tag, if known.
+    pub path: Option<&'a Path>,
+    /// The `(line, col)` position of the tag, or of its scenario if the tag has no recorded
+    /// location.
+    pub position: (usize, usize),
+}
+
+/// The references between scenarios written as tags, as resolved by [`resolve_links`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LinkGraph<'a> {
+    /// Every scenario, in the order the features were given, each feature's own scenarios
+    /// coming before those of its rules.
+    pub nodes: Vec<LinkNode<'a>>,
+    /// Each resolved reference, in the order of `nodes` and then of the tags.
+    pub links: Vec<Link>,
+    /// Each reference that could not be resolved, in the same order.
+    pub dangling: Vec<DanglingLink<'a>>,
+}
+
+/// Resolves the references between the scenarios of `features` written as tags.
+///
+/// A scenario declares an id with a tag starting with `id_prefix`, such as `@id:checkout` for
+/// a prefix of `id:`, and refers to the scenario declaring it with a tag starting with
+/// `ref_prefix`, such as `@depends-on:checkout`. Prefixes are given without the `@`. Only the
+/// scenario's own tags are read, not those of its feature, rule or examples. An id declared by
+/// several scenarios links to each of them.
+pub fn resolve_links<'a>(
+    features: &'a [Feature],
+    id_prefix: &str,
+    ref_prefix: &str,
+) -> LinkGraph<'a> {
+    let mut graph = LinkGraph {
+        nodes: vec![],
+        links: vec![],
+        dangling: vec![],
+    };
+
+    for feature in features {
+        let top = feature.scenarios.iter().map(|s| (None, s));
+        let nested = feature
+            .rules
+            .iter()
+            .flat_map(|r| r.scenarios().map(move |s| (Some(r), s)));
+        for (rule, scenario) in top.chain(nested) {
+            graph.nodes.push(LinkNode {
+                path: feature.path.as_deref(),
+                rule,
+                scenario,
+                scenario_id: feature.scenario_id(scenario),
+                ids: with_prefix(&scenario.tags, id_prefix)
+                    .map(|(_, id)| id.to_string())
+                    .collect(),
+            });
+        }
+    }
+
+    let mut declared: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, node) in graph.nodes.iter().enumerate() {
+        for id in node.ids.iter() {
+            declared.entry(id).or_default().push(i);
+        }
+    }
+
+    for (from, node) in graph.nodes.iter().enumerate() {
+        let scenario = node.scenario;
+        for (i, id) in with_prefix(&scenario.tags, ref_prefix) {
+            match declared.get(id) {
+                Some(targets) => graph
+                    .links
+                    .extend(targets.iter().map(|&to| Link { from, to })),
+                None => graph.dangling.push(DanglingLink {
+                    from,
+                    id: id.to_string(),
+                    path: node.path,
+                    position: scenario
+                        .tag_tokens
+                        .get(i)
+                        .map_or(scenario.position, |t| t.position),
+                }),
+            }
+        }
+    }
+
+    graph
+}
+
+fn with_prefix<'a>(
+    tags: &'a [String],
+    prefix: &'a str,
+) -> impl Iterator<Item = (usize, &'a str)> + 'a {
+    tags.iter()
+        .enumerate()
+        .filter_map(move |(i, t)| Some((i, t.strip_prefix(prefix)?)))
+        .filter(|(_, id)| !id.is_empty())
+}
+
+impl LinkGraph<'_> {
+    /// The indices in `nodes` of the scenarios `node` refers to, in the order of `links`, which
+    /// is taken to be ordered by `from` as [`resolve_links`] leaves it.
+    pub fn dependencies(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        let start = self.links.partition_point(|l| l.from < node);
+        let end = self.links.partition_point(|l| l.from <= node);
+        self.links[start..end].iter().map(|l| l.to)
+    }
+
+    /// A cycle of references, if there is one, as the indices in `nodes` of the scenarios in
+    /// it: each refers to the next, and the last to the first. A scenario referring to itself
+    /// is a cycle of one.
+    ///
+    /// Of several cycles, the first found searching from each node in order is given.
+    pub fn find_cycle(&self) -> Option<Vec<usize>> {
+        let mut adjacency = vec![vec![]; self.nodes.len()];
+        for link in self.links.iter() {
+            adjacency[link.from].push(link.to);
+        }
+
+        let mut state = vec![Visit::New; self.nodes.len()];
+        // The path being searched, with the number of each node's references followed so far.
+        let mut path: Vec<(usize, usize)> = vec![];
+
+        for start in 0..self.nodes.len() {
+            if state[start] != Visit::New {
+                continue;
+            }
+            state[start] = Visit::OnPath;
+            path.push((start, 0));
+
+            while let Some((node, followed)) = path.last_mut() {
+                let node = *node;
+                match adjacency[node].get(*followed) {
+                    Some(&next) => {
+                        *followed += 1;
+                        match state[next] {
+                            Visit::Done => {}
+                            Visit::OnPath => {
+                                let start = path.iter().position(|&(n, _)| n == next).unwrap();
+                                return Some(path[start..].iter().map(|&(n, _)| n).collect());
+                            }
+                            Visit::New => {
+                                state[next] = Visit::OnPath;
+                                path.push((next, 0));
+                            }
+                        }
+                    }
+                    None => {
+                        state[node] = Visit::Done;
+                        path.pop();
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    New,
+    OnPath,
+    Done,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn parse(path: &str, source: &str) -> Feature {
+        let mut feature = Feature::parse(source).unwrap();
+        feature.path = Some(PathBuf::from(path));
+        feature
+    }
+
+    #[test]
+    fn resolves_references_across_features() {
+        let features = [
+            parse(
+                "checkout.feature",
+                r"Feature: Checkout
+  @id:checkout-basic
+  Scenario: Basic
+    Given a basket
+
+  Rule: Refunds
+    @id:refund @depends-on:checkout-basic
+    Scenario: Refund
+      Given a paid order
+",
+            ),
+            parse(
+                "reports.feature",
+                r"Feature: Reports
+  @depends-on:refund @depends-on:invoicing
+  Scenario: Monthly
+    Given some refunds
+",
+            ),
+        ];
+        let graph = resolve_links(&features, "id:", "depends-on:");
+
+        assert_eq!(
+            graph
+                .nodes
+                .iter()
+                .map(|n| (n.scenario.name.as_str(), n.ids.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Basic", vec!["checkout-basic".to_string()]),
+                ("Refund", vec!["refund".to_string()]),
+                ("Monthly", vec![]),
+            ]
+        );
+        let refund = features[0].rules[0].scenarios().next().unwrap();
+        assert_eq!(graph.nodes[1].scenario_id, features[0].scenario_id(refund));
+        assert_eq!(graph.nodes[1].rule.map(|r| &*r.name), Some("Refunds"));
+        assert_eq!(
+            graph.links,
+            vec![Link { from: 1, to: 0 }, Link { from: 2, to: 1 }]
+        );
+        assert_eq!(
+            graph.dangling,
+            vec![DanglingLink {
+                from: 2,
+                id: "invoicing".to_string(),
+                path: Some(Path::new("reports.feature")),
+                position: (2, 22),
+            }]
+        );
+        assert_eq!(graph.find_cycle(), None);
+    }
+
+    #[test]
+    fn finds_cycles() {
+        let features = [Feature::parse(
+            r"Feature: Loops
+  @id:a @depends-on:c
+  Scenario: A
+    Given a
+
+  @id:b @depends-on:a
+  Scenario: B
+    Given b
+
+  @id:c @depends-on:b
+  Scenario: C
+    Given c
+
+  @id:d @depends-on:d
+  Scenario: D
+    Given d
+",
+        )
+        .unwrap()];
+        let graph = resolve_links(&features, "id:", "depends-on:");
+        assert_eq!(graph.find_cycle(), Some(vec![0, 2, 1]));
+
+        let features =
+            [
+                Feature::parse(
+                    "Feature: Self\n  @id:d @depends-on:d\n  Scenario: D\n    Given d\n",
+                )
+                .unwrap(),
+            ];
+        assert_eq!(
+            resolve_links(&features, "id:", "depends-on:").find_cycle(),
+            Some(vec![0])
+        );
+    }
+
+    #[test]
+    fn same_named_scenarios_are_separate_nodes() {
+        let features = [Feature::parse(
+            r"Feature: Names
+  @id:a
+  Scenario: S
+    Given a
+
+  Rule: R
+    @depends-on:a
+    Scenario: S
+      Given b
+",
+        )
+        .unwrap()];
+        let graph = resolve_links(&features, "id:", "depends-on:");
+
+        assert_ne!(graph.nodes[0].scenario_id, graph.nodes[1].scenario_id);
+        assert_eq!(graph.dependencies(1).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(graph.dependencies(0).count(), 0);
+    }
+
+    #[test]
+    fn long_chains_do_not_overflow_the_stack() {
+        let feature = Feature::parse("Feature: A\n  Scenario: B\n    Given c\n").unwrap();
+        let node = LinkNode {
+            path: None,
+            rule: None,
+            scenario: &feature.scenarios[0],
+            scenario_id: 0,
+            ids: vec![],
+        };
+        let len = 200_000;
+        let mut graph = LinkGraph {
+            nodes: vec![node; len],
+            links: (1..len).map(|i| Link { from: i - 1, to: i }).collect(),
+            dangling: vec![],
+        };
+        assert_eq!(graph.find_cycle(), None);
+
+        graph.links.push(Link {
+            from: len - 1,
+            to: 0,
+        });
+        assert_eq!(graph.find_cycle().map(|c| c.len()), Some(len));
+    }
+}
@@ -0,0 +1,360 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::{Feature, ParsedFeature, StepExt, TextEdit};
+
+/// How serious a [`Diagnostic`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found in a .feature file
+///
+/// Fields may be added to it, so outside this crate it is built with [`Diagnostic::new`]
+/// rather than as a struct literal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Diagnostic {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// A short, stable identifier for the kind of problem, such as `mixed-indentation`.
+    pub code: &'static str,
+    /// A description of the problem.
+    pub message: String,
+    /// The `(start, end)` offset of the problem in the .feature file.
+    pub span: (usize, usize),
+    /// The `(line, col)` position of the problem in the .feature file.
+    pub position: (usize, usize),
+    /// An edit to the .feature file that resolves the problem, if there is one. The edits of
+    /// the diagnostics from one check do not overlap, so can all be applied, last first.
+    pub fix: Option<TextEdit>,
+}
+
+impl Diagnostic {
+    /// A diagnostic with no fix.
+    pub fn new(
+        severity: Severity,
+        code: &'static str,
+        message: impl Into<String>,
+        span: (usize, usize),
+        position: (usize, usize),
+    ) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message: message.into(),
+            span,
+            position,
+            fix: None,
+        }
+    }
+
+    /// The diagnostic with `fix` as the edit resolving it.
+    pub fn with_fix(mut self, fix: TextEdit) -> Diagnostic {
+        self.fix = Some(fix);
+        self
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} [{}]",
+            self.position.0, self.position.1, self.message, self.code
+        )
+    }
+}
+
+impl ParsedFeature {
+    /// Lines whose indentation mixes tabs and spaces.
+    ///
+    /// The parser ignores indentation everywhere except inside docstrings, where a tab is taken
+    /// to reach the next multiple of eight columns; mixing the two there rarely lines up the way
+    /// it looks in an editor.
+    pub fn indentation_issues(&self) -> Vec<Diagnostic> {
+        let mut offset = 0;
+        let mut diagnostics = vec![];
+
+        for (i, line) in self.source.split('\n').enumerate() {
+            let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+            let whitespace = &line[..indent];
+
+            if whitespace.contains(' ') && whitespace.contains('\t') {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    code: "mixed-indentation",
+                    message: "indentation mixes tabs and spaces".to_string(),
+                    span: (offset, offset + indent),
+                    position: (i + 1, 1),
+                    fix: None,
+                });
+            }
+
+            offset += line.len() + 1;
+        }
+
+        diagnostics
+    }
+}
+
+impl ParsedFeature {
+    /// Table rows whose opening `|` is not in the same column as their table's header row,
+    /// each with a fix re-indenting the row like the header.
+    ///
+    /// The tables of steps spliced in from another file by an `# include:` comment, and rows
+    /// with no recorded position, are not checked.
+    pub fn table_indentation_issues(&self) -> Vec<Diagnostic> {
+        let mut line_starts = vec![0];
+        line_starts.extend(self.source.match_indices('\n').map(|(i, _)| i + 1));
+        let indent = |(line, column): (usize, usize)| {
+            let start = *line_starts.get(line.checked_sub(1)?)?;
+            Some((start, start + column.checked_sub(1)?))
+        };
+
+        let step_tables = self
+            .feature
+            .all_steps()
+            .filter(|s| s.origin.is_none())
+            .filter_map(|s| s.table.as_ref());
+        let examples_tables = self
+            .feature
+            .all_scenarios()
+            .flat_map(|s| s.examples.iter())
+            .filter_map(|e| e.table.as_ref());
+
+        let mut diagnostics = vec![];
+        for table in step_tables.chain(examples_tables) {
+            let header = match table.row_positions.first() {
+                Some(&header) => header,
+                None => continue,
+            };
+            let replacement = match indent(header) {
+                Some(span) => self.text_of(span).to_string(),
+                None => continue,
+            };
+
+            for &row in table.row_positions.iter().filter(|p| p.1 != header.1) {
+                let span = match indent(row) {
+                    Some(span) => span,
+                    None => continue,
+                };
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    code: "misaligned-table-row",
+                    message: format!(
+                        "table row starts in column {}, but its header starts in column {}",
+                        row.1, header.1
+                    ),
+                    span,
+                    position: (row.0, 1),
+                    fix: Some(TextEdit {
+                        span,
+                        replacement: replacement.clone(),
+                    }),
+                });
+            }
+        }
+
+        diagnostics.sort_by_key(|d| d.span);
+        diagnostics
+    }
+}
+
+impl Feature {
+    /// Steps whose value differs from its [`normalized_value`](crate::StepExt::normalized_value),
+    /// because of doubled, non-breaking or other unusual spaces, in document order.
+    pub fn step_spacing_issues(&self) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<_> = self
+            .all_steps()
+            .filter(|step| step.value != step.normalized_value())
+            .map(|step| Diagnostic {
+                severity: Severity::Warning,
+                code: "irregular-step-spacing",
+                message: format!(
+                    "step text {:?} has irregular spacing; it reads as {:?}",
+                    step.value,
+                    step.normalized_value()
+                ),
+                span: step.span,
+                position: step.position,
+                fix: None,
+            })
+            .collect();
+        diagnostics.sort_by_key(|d| d.span);
+        diagnostics
+    }
+
+    /// The tags that `is_valid` rejects, such as those breaking a naming convention, in
+    /// document order. `is_valid` is given each tag as written, with its `@`, so a pattern
+    /// such as `@[a-z0-9_-]+` can be checked with a regex crate or by hand.
+    ///
+    /// Only tags with recorded positions are checked, which includes every tag parsed from a
+    /// .feature file.
+    pub fn validate_tag_syntax(&self, is_valid: impl Fn(&str) -> bool) -> Vec<Diagnostic> {
+        let rules = self.rules.iter().flat_map(|r| r.tag_tokens.iter());
+        let scenarios = self.all_scenarios().flat_map(|s| {
+            s.tag_tokens
+                .iter()
+                .chain(s.examples.iter().flat_map(|e| e.tag_tokens.iter()))
+        });
+
+        let mut diagnostics: Vec<_> = self
+            .tag_tokens
+            .iter()
+            .chain(rules)
+            .chain(scenarios)
+            .map(|tag| (format!("@{}", tag.name), tag))
+            .filter(|(written, _)| !is_valid(written))
+            .map(|(written, tag)| Diagnostic {
+                severity: Severity::Warning,
+                code: "invalid-tag",
+                message: format!("tag {} does not follow the tag convention", written),
+                span: tag.span,
+                position: tag.position,
+                fix: None,
+            })
+            .collect();
+        diagnostics.sort_by_key(|d| d.span);
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn flags_mixed_indentation() {
+        let source =
+            "Feature: Tabs\n  Scenario: Mixed\n\t  Given a tab and spaces\n\t\tWhen only tabs\n";
+        let parsed = Feature::parse_with_source(source.to_string()).unwrap();
+        let issues = parsed.indentation_issues();
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].position, (3, 1));
+        assert_eq!(parsed.text_of(issues[0].span), "\t  ");
+    }
+
+    #[test]
+    fn flags_tags_breaking_the_convention() {
+        let feature = Feature::parse(
+            r"@billing @Team-Web
+Feature: Tags
+
+  @smoke @WIP
+  Scenario Outline: B
+    Given <n>
+
+    @rows_1
+    Examples:
+      | n |
+      | 1 |
+",
+        )
+        .unwrap();
+        let lowercase = |tag: &str| {
+            tag.len() > 1
+                && tag[1..]
+                    .chars()
+                    .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_' | '-'))
+        };
+
+        let issues: Vec<_> = feature
+            .validate_tag_syntax(lowercase)
+            .into_iter()
+            .map(|d| (d.position, d.message))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    (1, 10),
+                    "tag @Team-Web does not follow the tag convention".to_string()
+                ),
+                (
+                    (4, 10),
+                    "tag @WIP does not follow the tag convention".to_string()
+                ),
+            ]
+        );
+
+        // A space ends a tag, so `@Bad Tag` is a tag followed by text, which is not valid on a
+        // tag line.
+        assert!(Feature::parse("@Bad Tag\nFeature: A\n").is_err());
+    }
+
+    #[test]
+    fn flags_irregular_step_spacing() {
+        let feature = Feature::parse(
+            "Feature: Spacing\n  Background:\n    Given a\u{a0}user\n\n  Scenario: A\n    When they  log in\n    Then they see\u{3000}the page\n    And all is well\n",
+        )
+        .unwrap();
+        let steps = &feature.scenarios[0].steps;
+        assert_eq!(steps[0].normalized_value(), "they log in");
+        assert_eq!(steps[1].normalized_value(), "they see the page");
+
+        let lines: Vec<_> = feature
+            .step_spacing_issues()
+            .into_iter()
+            .map(|d| d.position.0)
+            .collect();
+        assert_eq!(lines, vec![3, 6, 7]);
+    }
+
+    #[test]
+    fn flags_and_fixes_misaligned_table_rows() {
+        let source = r"Feature: Tables
+  Scenario Outline: A
+    Given <a>
+      | x | y |
+        | 1 | 2 |
+      | 3 | 4 |
+
+    Examples:
+      | a |
+    | 1 |
+";
+        let parsed = Feature::parse_with_source(source.to_string()).unwrap();
+        let step_table = parsed.feature.scenarios[0].steps[0].table.as_ref().unwrap();
+        assert_eq!(step_table.indentation(), None);
+
+        let issues = parsed.table_indentation_issues();
+        let lines: Vec<_> = issues.iter().map(|d| d.position.0).collect();
+        assert_eq!(lines, vec![5, 10]);
+
+        let fixed = issues
+            .iter()
+            .rev()
+            .filter_map(|d| d.fix.as_ref())
+            .fold(source.to_string(), |source, fix| fix.apply(&source));
+        let parsed = Feature::parse_with_source(fixed).unwrap();
+        assert!(parsed.table_indentation_issues().is_empty());
+        let step_table = parsed.feature.scenarios[0].steps[0].table.as_ref().unwrap();
+        assert_eq!(step_table.indentation(), Some(7));
+    }
+
+    #[test]
+    fn skips_included_and_positionless_tables() {
+        let options = crate::ParseOptions::default().with_include_resolver(|_: &str| {
+            Ok("\n\n\n\n\n\n\nBackground:\n  Given a table\n    | a |\n      | 1 |\n".to_string())
+        });
+        let source = "# include: table.background\nFeature: Short\n";
+        let parsed = Feature::parse_with_options(source.to_string(), &options).unwrap();
+        assert!(parsed.feature.background.is_some());
+        assert!(parsed.table_indentation_issues().is_empty());
+
+        let source = "Feature: Tables\n  Scenario: A\n    Given a\n      | x |\n        | 1 |\n";
+        let mut parsed = Feature::parse_with_source(source.to_string()).unwrap();
+        assert_eq!(parsed.table_indentation_issues().len(), 1);
+        parsed.feature.strip_positions();
+        assert!(parsed.table_indentation_issues().is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::include::IncludeResolver;
+use crate::{Diagnostic, Feature, Severity};
+
+/// How closely to follow the reference Gherkin implementation
+///
+/// Where the two modes differ:
+///
+/// - Table rows with a different number of cells to the first row are accepted (and cut to
+///   its length) when permissive, and rejected when strict.
+/// - The `formal` language, which is particular to this crate, is only available when
+///   permissive.
+/// - A table row with text outside its cells is skipped with a warning when permissive, and
+///   rejected when strict.
+/// - A docstring that is never closed ends before the next tag, block keyword or step line
+///   indented no further than its opening delimiter with a warning when permissive, and is
+///   rejected at its opening delimiter when strict. When permissive, a docstring is also taken
+///   to be unclosed if such a line indented less than the delimiter comes before the next
+///   delimiter.
+/// - A table wider than [`ParseOptions::with_max_table_columns`] allows is kept whole with an
+///   error diagnostic when permissive, and rejected with a
+///   [`ParseSourceError::TableTooWide`](crate::ParseSourceError::TableTooWide) when strict.
+/// - A rule's background may follow some of its scenarios when permissive, and must come before
+///   them when strict.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ParseMode {
+    /// Accepts the variations listed above, recovering from them with a diagnostic where
+    /// noted. This is the default.
+    #[default]
+    Permissive,
+    /// Rejects the variations listed above, and nothing else. The grammar is otherwise the
+    /// same as when permissive, so this is not a guarantee of agreeing with the reference
+    /// implementation on every file.
+    Strict,
+}
+
+/// Options controlling how a .feature file is parsed
+///
+/// See [`Feature::parse_with_options`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    mode: ParseMode,
+    case_insensitive_keywords: bool,
+    normalize_keyword_case: bool,
+    metrics: bool,
+    sub_steps: bool,
+    title_on_next_line: bool,
+    table_delimiter: Option<char>,
+    max_table_columns: Option<usize>,
+    unknown_keyword_as_star: bool,
+    skip_descriptions: bool,
+    include_resolver: Option<IncludeResolver>,
+}
+
+impl ParseOptions {
+    /// Which [`ParseMode`] to parse in.
+    pub fn with_mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub(crate) fn mode(&self) -> ParseMode {
+        self.mode
+    }
+
+    /// Whether step keywords are matched ignoring case, so `given` is read as `Given`. Disabled
+    /// by default, in which case a keyword must be written as the dialect spells it.
+    pub fn with_case_insensitive_keywords(mut self, enabled: bool) -> Self {
+        self.case_insensitive_keywords = enabled;
+        self
+    }
+
+    pub(crate) fn case_insensitive_keywords(&self) -> bool {
+        self.case_insensitive_keywords
+    }
+
+    /// Whether to store the dialect's spelling of each step keyword in [`Step::raw_type`]
+    /// (`Given`), rather than the spelling used in the source (`given`), which can only differ
+    /// with [`with_case_insensitive_keywords`](ParseOptions::with_case_insensitive_keywords).
+    /// Disabled by default.
+    ///
+    /// [`Step::raw_type`]: crate::Step::raw_type
+    pub fn with_normalized_keyword_case(mut self, enabled: bool) -> Self {
+        self.normalize_keyword_case = enabled;
+        self
+    }
+
+    pub(crate) fn normalize_keyword_case(&self) -> bool {
+        self.normalize_keyword_case
+    }
+
+    /// Whether to collect [`ParseMetrics`] for the parse. Disabled by default, in which case
+    /// no timing or counting is done.
+    pub fn with_metrics(mut self, enabled: bool) -> Self {
+        self.metrics = enabled;
+        self
+    }
+
+    pub(crate) fn metrics(&self) -> bool {
+        self.metrics
+    }
+
+    /// Whether `*` steps indented further than the step before them are read as its
+    /// [`Step::sub_steps`], as bullet points under it, rather than as steps of their own.
+    /// Disabled by default.
+    ///
+    /// Sub-steps take the type of their parent step and may have docstrings, tables and
+    /// sub-steps of their own.
+    ///
+    /// [`Step::sub_steps`]: crate::Step::sub_steps
+    pub fn with_sub_steps(mut self, enabled: bool) -> Self {
+        self.sub_steps = enabled;
+        self
+    }
+
+    pub(crate) fn sub_steps(&self) -> bool {
+        self.sub_steps
+    }
+
+    /// Whether a `Feature:` line without a name takes its name from the next line of text.
+    /// Disabled by default, in which case the name is empty and that line starts the
+    /// description.
+    pub fn with_title_on_next_line(mut self, enabled: bool) -> Self {
+        self.title_on_next_line = enabled;
+        self
+    }
+
+    pub(crate) fn title_on_next_line(&self) -> bool {
+        self.title_on_next_line
+    }
+
+    /// The character separating the cells of tables, in place of `|`.
+    ///
+    /// A line following a step or `Examples:` line, or another table row, is read as a table
+    /// row when, after any leading spaces, it starts and ends with `delimiter`, so with a tab
+    /// delimiter the rows must be indented with spaces. Cells may not then contain a `|`.
+    /// Descriptions and docstrings are left as they are.
+    ///
+    /// # Panics
+    ///
+    /// If `delimiter` is not an ASCII character.
+    pub fn with_table_delimiter(mut self, delimiter: char) -> Self {
+        assert!(delimiter.is_ascii(), "table delimiters must be ASCII");
+        self.table_delimiter = Some(delimiter).filter(|d| *d != '|');
+        self
+    }
+
+    pub(crate) fn table_delimiter(&self) -> Option<char> {
+        self.table_delimiter
+    }
+
+    /// The most columns a table may have. Unlimited by default.
+    ///
+    /// A wider table is rejected with a
+    /// [`ParseSourceError::TableTooWide`](crate::ParseSourceError::TableTooWide) when strict,
+    /// unless the parse has already failed before it. When permissive, it is kept
+    /// whole with an error diagnostic giving the [`TableTooWide`](crate::TableTooWide) found
+    /// by [`Table::check_width`](crate::Table::check_width).
+    pub fn with_max_table_columns(mut self, max: usize) -> Self {
+        self.max_table_columns = Some(max);
+        self
+    }
+
+    pub(crate) fn max_table_columns(&self) -> Option<usize> {
+        self.max_table_columns
+    }
+
+    /// Whether a line in a list of steps starting with a word that is not a step keyword is
+    /// read as a step with that word as its keyword, continuing the previous step's type like
+    /// `*`. Disabled by default, in which case the line ends the steps.
+    ///
+    /// Lines starting a block, a tag, a comment, a table row or a docstring are never read as
+    /// steps, and the first step of a list must still have a keyword giving its type.
+    pub fn with_unknown_keyword_as_star(mut self, enabled: bool) -> Self {
+        self.unknown_keyword_as_star = enabled;
+        self
+    }
+
+    pub(crate) fn unknown_keyword_as_star(&self) -> bool {
+        self.unknown_keyword_as_star
+    }
+
+    /// Whether to keep the descriptions of the feature, its rules and backgrounds. Enabled by
+    /// default; when disabled the description lines are still read past, but `description` is
+    /// always `None`.
+    pub fn with_capture_descriptions(mut self, enabled: bool) -> Self {
+        self.skip_descriptions = !enabled;
+        self
+    }
+
+    pub(crate) fn capture_descriptions(&self) -> bool {
+        !self.skip_descriptions
+    }
+
+    /// Enables the `# include: <path>` convention for sharing backgrounds, which is particular
+    /// to this crate. Disabled by default, in which case such lines are ordinary comments.
+    ///
+    /// Each include comment before the `Feature:` line is looked up with `resolver`, which
+    /// returns the text of a file holding a single `Background:`. Its steps are placed before
+    /// the feature's own background steps, with [`Step::origin`] set to the include path.
+    /// Included files may include others; failures and cycles are reported as error
+    /// diagnostics and the include is skipped.
+    ///
+    /// [`Step::origin`]: crate::Step::origin
+    pub fn with_include_resolver(
+        mut self,
+        resolver: impl Fn(&str) -> io::Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.include_resolver = Some(IncludeResolver(Arc::new(resolver)));
+        self
+    }
+
+    pub(crate) fn include_resolver(&self) -> Option<&IncludeResolver> {
+        self.include_resolver.as_ref()
+    }
+}
+
+/// Options controlling how scenario outlines are expanded
+///
+/// See [`Scenario::expand_examples_with`](crate::Scenario::expand_examples_with).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExpandOptions {
+    placeholder_delimiters: Vec<(String, String)>,
+}
+
+impl Default for ExpandOptions {
+    fn default() -> Self {
+        ExpandOptions {
+            placeholder_delimiters: vec![("<".to_string(), ">".to_string())],
+        }
+    }
+}
+
+impl ExpandOptions {
+    /// The `(open, close)` pairs that may surround a placeholder name, such as `("${", "}")`
+    /// for `${name}`. Only `("<", ">")` by default. Pairs with an empty delimiter are ignored.
+    ///
+    /// The text is read left to right. At each position the pairs are tried in order, and the
+    /// first whose `open` starts there and whose next `close` ends a column name is replaced;
+    /// the name may itself contain `open`. Otherwise the character is kept and the next
+    /// position is tried, so with the default pair `<<a>>` becomes `<`, the value of `a`,
+    /// then `>`.
+    pub fn with_placeholder_delimiters<O, C>(
+        mut self,
+        delimiters: impl IntoIterator<Item = (O, C)>,
+    ) -> Self
+    where
+        O: Into<String>,
+        C: Into<String>,
+    {
+        self.placeholder_delimiters = delimiters
+            .into_iter()
+            .map(|(open, close)| (open.into(), close.into()))
+            .filter(|(open, close)| !open.is_empty() && !close.is_empty())
+            .collect();
+        self
+    }
+
+    pub(crate) fn placeholder_delimiters(&self) -> &[(String, String)] {
+        &self.placeholder_delimiters
+    }
+}
+
+/// Measurements of a single parse, collected when enabled with [`ParseOptions::with_metrics`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseMetrics {
+    /// The time taken to parse the source.
+    pub duration: Duration,
+    /// The length of the source in bytes.
+    pub bytes: usize,
+    /// The number of scenarios, including those inside rules.
+    pub scenarios: usize,
+    /// The number of steps, including background steps.
+    pub steps: usize,
+    /// The number of cells across all step tables and examples tables, headers included.
+    pub table_cells: usize,
+    /// The number of errors recovered from while parsing.
+    pub errors: usize,
+    /// The number of warnings raised while parsing.
+    pub warnings: usize,
+}
+
+impl ParseMetrics {
+    pub(crate) fn new(
+        feature: &Feature,
+        diagnostics: &[Diagnostic],
+        bytes: usize,
+        duration: Duration,
+    ) -> ParseMetrics {
+        let count = |severity| {
+            diagnostics
+                .iter()
+                .filter(|d| d.severity == severity)
+                .count()
+        };
+        let step_tables = feature.all_steps().filter_map(|s| s.table.as_ref());
+        let examples_tables = feature
+            .all_scenarios()
+            .flat_map(|s| s.examples.iter())
+            .filter_map(|e| e.table.as_ref());
+        let table_cells = step_tables
+            .chain(examples_tables)
+            .flat_map(|t| t.rows.iter())
+            .map(|r| r.len())
+            .sum();
+
+        ParseMetrics {
+            duration,
+            bytes,
+            scenarios: feature.all_scenarios().count(),
+            steps: feature.all_steps().count(),
+            table_cells,
+            errors: count(Severity::Error),
+            warnings: count(Severity::Warning),
+        }
+    }
+}
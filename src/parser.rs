@@ -6,116 +6,238 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::cell::RefCell;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
 
-use crate::tagexpr::TagOperation;
-use crate::{Background, Examples, Feature, Rule, Scenario, Step, StepType, Table};
-
-struct Keywords<'a> {
-    feature: &'a [&'a str],
-    background: &'a [&'a str],
-    rule: &'a [&'a str],
-    scenario: &'a [&'a str],
-    scenario_outline: &'a [&'a str],
-    examples: &'a [&'a str],
-    given: &'a [&'a str],
-    when: &'a [&'a str],
-    then: &'a [&'a str],
-    and: &'a [&'a str],
-    but: &'a [&'a str],
-}
-
-impl<'a> Keywords<'a> {
-    pub fn all(&self) -> Vec<&'a str> {
-        let mut v = vec![];
-
-        for x in [
-            self.feature,
-            self.background,
-            self.rule,
-            self.scenario,
-            self.rule,
-            self.scenario_outline,
-            self.examples,
-            self.given,
-            self.when,
-            self.then,
-            self.and,
-            self.but,
-        ]
-        .iter()
-        {
-            v.append(&mut x.to_vec());
-        }
-
-        v
-    }
-}
-
-const DEFAULT_KEYWORDS: Keywords<'static> = Keywords {
-    feature: &["Feature"],
-    background: &["Background"],
-    rule: &["Rule"],
-    scenario: &["Scenario", "Example"],
-    scenario_outline: &["Scenario Outline", "Scenario Template"],
-    examples: &["Examples"],
-    given: &["Given"],
-    when: &["When"],
-    then: &["Then"],
-    and: &["*", "And"],
-    but: &["But"],
-};
+use peg::error::ParseError;
+use peg::str::LineCol;
 
-const FORMAL_SPEC_KEYWORDS: Keywords<'static> = Keywords {
-    feature: &["Section"],
-    background: &["Context"],
-    rule: &["Rule"],
-    scenario: &["Proof", "Evidence"],
-    scenario_outline: &["Demonstration"],
-    examples: &["Examples"],
-    given: &["Given"],
-    when: &["When"],
-    then: &["Then"],
-    and: &["*", "And"],
-    but: &["But"],
+use crate::dialect::{find_keyword, Dialect, Keywords};
+use crate::lint::{Diagnostic, Severity};
+use crate::options::{ParseMode, ParseOptions};
+use crate::tagexpr::TagOperation;
+use crate::token::{token_scan, TokenKind};
+use crate::{
+    Background, DocstringDelimiter, Examples, Feature, Rule, RuleChild, Scenario, Step, StepType,
+    Table, TableTooWide, Tag,
 };
 
-const SV_KEYWORDS: Keywords<'static> = Keywords {
-    feature: &["Egenskap"],
-    background: &["Bakgrund"],
-    rule: &["Regel"],
-    scenario: &["Scenario", "Exempel"],
-    scenario_outline: &["Abstrakt Scenario"],
-    examples: &["Exempel"],
-    given: &["Givet"],
-    when: &["När"],
-    then: &["Så"],
-    and: &["*", "Och"],
-    but: &["Men"],
-};
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Blank,
+    Comment,
+    Content,
+}
 
 pub struct GherkinEnv {
+    options: ParseOptions,
     keywords: RefCell<Keywords<'static>>,
     last_step: RefCell<Option<StepType>>,
     last_keyword: RefCell<Option<String>>,
-    line_offsets: RefCell<Vec<usize>>,
+    line_offsets: Vec<usize>,
+    line_kinds: Vec<LineKind>,
+    line_indents: Vec<usize>,
+    /// The text after the `#` of each comment line, by line number.
+    comments: BTreeMap<usize, String>,
+    /// The delimiter starting each line that starts with one, by line number.
+    fences: BTreeMap<usize, DocstringDelimiter>,
+    /// The problems recovered from so far, in the order they were found.
+    diagnostics: RefCell<Vec<Diagnostic>>,
+    /// The grammar rules that have matched so far, by name, when built for
+    /// [`coverage`](crate::coverage).
+    hits: RefCell<Vec<&'static str>>,
+    /// The offset of a mistake found while parsing, and what was expected there instead, to
+    /// report if the parse fails rather than wherever the parser finally gave up. It is
+    /// forgotten once a block ending past it is parsed, as the parse got past the mistake.
+    pinned_error: Cell<Option<(usize, &'static str)>>,
+    /// The table rejected for being too wide when strict, with its offset, to report in place
+    /// of the pinned error it set if that is what the parse fails with.
+    table_too_wide: RefCell<Option<(usize, TableTooWide)>>,
+    trailing_newline: bool,
 }
 
 impl GherkinEnv {
+    pub fn new(input: &str) -> GherkinEnv {
+        GherkinEnv::with_options(input, ParseOptions::default())
+    }
+
+    pub fn with_options(input: &str, options: ParseOptions) -> GherkinEnv {
+        let mut line_offsets = vec![0];
+        line_offsets.extend(input.match_indices('\n').map(|(i, _)| i + 1));
+
+        let line_indents = input.split('\n').map(indent_width).collect();
+        let line_kinds = input
+            .split('\n')
+            .map(|line| {
+                let line = line.trim();
+                if line.is_empty() {
+                    LineKind::Blank
+                } else if line.starts_with('#') {
+                    LineKind::Comment
+                } else {
+                    LineKind::Content
+                }
+            })
+            .collect();
+
+        let comments = input
+            .split('\n')
+            .enumerate()
+            .filter_map(|(i, line)| {
+                Some((i + 1, line.trim().strip_prefix('#')?.trim().to_string()))
+            })
+            .collect();
+
+        let fences = input
+            .split('\n')
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let line = line.trim_start();
+                let delimiter = [DocstringDelimiter::Quotes, DocstringDelimiter::Backticks]
+                    .iter()
+                    .find(|d| line.starts_with(d.as_str()))?;
+                Some((i + 1, *delimiter))
+            })
+            .collect();
+
+        GherkinEnv {
+            options,
+            keywords: RefCell::new(Dialect::default().keywords),
+            last_step: RefCell::new(None),
+            last_keyword: RefCell::new(None),
+            line_offsets,
+            line_kinds,
+            line_indents,
+            comments,
+            fences,
+            diagnostics: RefCell::new(vec![]),
+            hits: RefCell::new(vec![]),
+            pinned_error: Cell::new(None),
+            table_too_wide: RefCell::new(None),
+            trailing_newline: input.ends_with('\n'),
+        }
+    }
+
+    /// The problems recovered from while parsing, in document order. A problem found more than
+    /// once at the same offset is only given once.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let diagnostics = self.diagnostics.borrow();
+        let by_offset: BTreeMap<_, _> = diagnostics.iter().map(|d| (d.span.0, d)).collect();
+        by_offset.into_values().cloned().collect()
+    }
+
+    /// The number of problems recorded and rules hit so far, to pass to `backtrack`.
+    fn mark(&self) -> (usize, usize) {
+        (self.diagnostics.borrow().len(), self.hits.borrow().len())
+    }
+
+    /// Forgets the problems recorded and rules hit since `mark`, as the input they were found
+    /// in has been backtracked over.
+    fn backtrack(&self, mark: (usize, usize)) {
+        self.diagnostics.borrow_mut().truncate(mark.0);
+        self.hits.borrow_mut().truncate(mark.1);
+    }
+
+    /// Records that the grammar rule `name` matched, when built for
+    /// [`coverage`](crate::coverage).
+    fn hit(&self, name: &'static str) {
+        if cfg!(any(test, feature = "test-utils")) {
+            self.hits.borrow_mut().push(name);
+        }
+    }
+
+    /// The names of the grammar rules that matched, in the order they did.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub(crate) fn hits(&self) -> Vec<&'static str> {
+        self.hits.borrow().clone()
+    }
+
+    /// Records a problem at `span`. It is only kept if the step or rule it was found in is
+    /// parsed; see the `committed` rule.
+    fn warn(&self, code: &'static str, message: &str, span: (usize, usize)) {
+        self.diagnose(Severity::Warning, code, message, span);
+    }
+
+    fn diagnose(
+        &self,
+        severity: Severity,
+        code: &'static str,
+        message: &str,
+        span: (usize, usize),
+    ) {
+        let diagnostic = Diagnostic {
+            severity,
+            code,
+            message: message.to_string(),
+            span,
+            position: self.position(span.0),
+            fix: None,
+        };
+        self.diagnostics.borrow_mut().push(diagnostic);
+    }
+
     pub fn set_language(&self, language: &str) -> Result<(), &'static str> {
-        let keywords = match language {
-            "formal" => FORMAL_SPEC_KEYWORDS,
-            "sv" => SV_KEYWORDS,
-            "en" => DEFAULT_KEYWORDS,
-            _ => return Err("Error: requested language not supported"),
+        let dialect = Dialect::from_language(language)
+            .filter(|d| !self.strict() || d.is_standard())
+            .ok_or("Error: requested language not supported")?;
+
+        *self.keywords.borrow_mut() = dialect.keywords;
+
+        Ok(())
+    }
+
+    /// Applies [`ParseOptions::with_max_table_columns`] to `table`. A wider table fails to
+    /// parse when strict, and is kept whole with an error diagnostic when permissive.
+    fn check_table_width(&self, table: &Table) -> Result<(), &'static str> {
+        let error = match self.options.max_table_columns() {
+            Some(max) => match table.check_width(max) {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            },
+            None => return Ok(()),
         };
 
-        *self.keywords.borrow_mut() = keywords;
+        if self.strict() {
+            self.pinned_error.set(Some((table.span.0, TABLE_TOO_WIDE)));
+            *self.table_too_wide.borrow_mut() = Some((table.span.0, error));
+            return Err("fewer table columns");
+        }
 
+        self.diagnose(
+            Severity::Error,
+            "too-many-columns",
+            &error.to_string(),
+            table.span,
+        );
         Ok(())
     }
 
-    fn keywords(&self) -> std::cell::Ref<Keywords<'static>> {
+    /// Forgets the pinned error if it lies before `offset`, which a block was parsed up to.
+    fn recovered_past(&self, offset: usize) {
+        if matches!(self.pinned_error.get(), Some((pinned, _)) if pinned < offset) {
+            self.pinned_error.set(None);
+        }
+    }
+
+    /// The table rejected for being too wide, if that is the mistake the parse failed with.
+    pub fn table_too_wide(&self) -> Option<TableTooWide> {
+        match (
+            self.pinned_error.get(),
+            self.table_too_wide.borrow().as_ref(),
+        ) {
+            (Some((pinned, TABLE_TOO_WIDE)), Some((offset, error))) if pinned == *offset => {
+                Some(error.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn strict(&self) -> bool {
+        self.options.mode() == ParseMode::Strict
+    }
+
+    fn keywords(&self) -> std::cell::Ref<'_, Keywords<'static>> {
         self.keywords.borrow()
     }
 
@@ -127,7 +249,7 @@ impl GherkinEnv {
         *self.last_keyword.borrow_mut() = None;
     }
 
-    fn last_keyword(&self) -> std::cell::Ref<Option<String>> {
+    fn last_keyword(&self) -> std::cell::Ref<'_, Option<String>> {
         self.last_keyword.borrow()
     }
 
@@ -147,55 +269,267 @@ impl GherkinEnv {
         *self.last_step.borrow()
     }
 
-    fn increment_nl(&self, offset: usize) {
-        self.line_offsets.borrow_mut().push(offset);
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_offsets.partition_point(|x| *x <= offset);
+        (line, offset - self.line_offsets[line - 1] + 1)
     }
 
-    fn position(&self, offset: usize) -> (usize, usize) {
-        let line_offsets = self.line_offsets.borrow();
-        let index = line_offsets.iter().position(|x| x > &offset);
+    /// The indentation width of the line containing `offset`, in columns.
+    fn indent_width(&self, offset: usize) -> usize {
+        self.line_indents[self.position(offset).0 - 1]
+    }
 
-        let line = index.unwrap_or(0);
-        let col = index.map(|i| offset - line_offsets[i - 1]).unwrap_or(offset) + 1;
+    /// Warns about each of `examples` indented less than the scenario named `name` at `offset`,
+    /// which is usually meant to apply to several outlines but applies to that one alone.
+    fn warn_dedented_examples(&self, offset: usize, name: &str, examples: &[Examples]) {
+        let indent = self.indent_width(offset);
+
+        for e in examples
+            .iter()
+            .filter(|e| self.indent_width(e.span.0) < indent)
+        {
+            let message = format!(
+                "examples block is indented less than its scenario {:?} but applies to it alone; \
+                 each outline needs its own examples",
+                name
+            );
+            self.warn("dedented-examples", &message, e.span);
+        }
+    }
 
-        (line, col)
+    /// The text of the description made of `lines`, or `None` if it is blank or descriptions
+    /// are not being captured.
+    fn description(&self, lines: Vec<&str>) -> Option<String> {
+        Some(lines.join("\n")).filter(|d| self.options.capture_descriptions() && d.trim() != "")
     }
+
+    /// Whether a docstring opened with `delimiter` at `offset` has a closing delimiter, once the
+    /// delimiters after it are paired up from the end of the input back to it. A later
+    /// docstring that is itself closed does not close this one.
+    fn closing_fence_follows(&self, offset: usize, delimiter: DocstringDelimiter) -> bool {
+        let mut open = None;
+        for (_, fence) in self.fences.range(self.position(offset).0 + 1..).rev() {
+            match open {
+                None => open = Some(*fence),
+                Some(d) if d == *fence => open = None,
+                Some(_) => {}
+            }
+        }
+        open == Some(delimiter)
+    }
+
+    /// The number of blank lines directly above the line containing `offset`, looking past
+    /// any comment lines in between.
+    fn leading_blank_lines(&self, offset: usize) -> usize {
+        let line = self.position(offset).0;
+
+        self.line_kinds[..line - 1]
+            .iter()
+            .rev()
+            .take_while(|x| **x != LineKind::Content)
+            .filter(|x| **x == LineKind::Blank)
+            .count()
+    }
+
+    /// The comment lines directly above the line containing `offset`.
+    fn comments_above(&self, offset: usize) -> Vec<String> {
+        let line = self.position(offset).0;
+        let mut comments: Vec<_> = (1..line)
+            .rev()
+            .map_while(|l| self.comments.get(&l).cloned())
+            .collect();
+        comments.reverse();
+        comments
+    }
+}
+
+/// A row of a table as parsed, before the rows are gathered into a [`Table`].
+struct TableRow {
+    position: (usize, usize),
+    cells: Vec<String>,
+    comment: Option<String>,
+    leading_comments: Vec<String>,
+}
+
+/// What a strict parse expected in place of a table wider than the limit.
+const TABLE_TOO_WIDE: &str = "table with no more columns than the limit";
+
+/// Tabs advance to the next multiple of this many columns when measuring indentation.
+const TAB_WIDTH: usize = 8;
+
+fn advance_column(col: usize, c: char) -> usize {
+    if c == '\t' {
+        (col / TAB_WIDTH + 1) * TAB_WIDTH
+    } else {
+        col + 1
+    }
+}
+
+/// The width in columns of the whitespace at the start of `line`.
+fn indent_width(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .fold(0, advance_column)
+}
+
+/// Removes up to `width` columns of leading whitespace from every line of a docstring after its
+/// first (which follows the opening delimiter). A tab straddling `width` is replaced by the
+/// spaces that extend past it, so content indented beyond the delimiter keeps its alignment.
+fn unindent(docstring: &str, width: usize) -> String {
+    let mut lines = docstring.split('\n');
+    let mut out = lines.next().unwrap_or_default().to_string();
+
+    for line in lines {
+        out.push('\n');
+
+        let mut col = 0;
+        let mut rest = line;
+        for (i, c) in line.char_indices() {
+            if col >= width || (c != ' ' && c != '\t') {
+                rest = &line[i..];
+                break;
+            }
+
+            col = advance_column(col, c);
+            rest = &line[i + c.len_utf8()..];
+        }
+
+        out.extend(std::iter::repeat_n(' ', col.saturating_sub(width)));
+        out.push_str(rest);
+    }
+
+    out
+}
+
+/// The text of a docstring between `delimiter`s, unindented by `width` columns and with any
+/// escaped delimiters unescaped.
+fn docstring_text(docstring: &str, width: usize, delimiter: DocstringDelimiter) -> String {
+    unindent(docstring, width).replace(delimiter.escaped(), delimiter.as_str())
+}
+
+/// The value of a table cell as written, with `\|`, `\\` and `\n` read as a pipe, a backslash
+/// and a newline. A backslash before any other character is kept.
+fn unescape_cell(cell: &str) -> String {
+    let mut out = String::with_capacity(cell.len());
+    let mut chars = cell.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('|') => out.push('|'),
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+/// Parses a feature. When a docstring is never closed, or is given in place of an examples
+/// table, the error points at its opening delimiter rather than wherever the parser finally
+/// gave up. Spans end before the final line ending, if there is one.
+pub(crate) fn parse(input: &str, env: &GherkinEnv) -> Result<Feature, ParseError<LineCol>> {
+    let mut feature =
+        gherkin_parser::feature(input, env).map_err(|e| match env.pinned_error.get() {
+            Some((offset, expected)) => {
+                let chars = input[..offset].chars().count();
+                gherkin_parser::error_at(input, env, chars, expected).unwrap_err()
+            }
+            None => e,
+        })?;
+
+    let content = input.strip_suffix('\n').unwrap_or(input);
+    feature.end_spans_at(content.strip_suffix('\r').unwrap_or(content).len());
+    Ok(feature)
+}
+
+/// The input as the grammar reads it: with each lone `\r` line ending replaced by `\n`, and
+/// the table delimiter set in `options` replaced by `|` in each table row outside docstrings.
+/// The replacements are the same length, so offsets into it are offsets into `input`.
+pub(crate) fn prepare<'a>(input: &'a str, options: &ParseOptions) -> Cow<'a, str> {
+    let bytes = input.as_bytes();
+    let lone_cr = |i: usize| bytes[i] == b'\r' && bytes.get(i + 1) != Some(&b'\n');
+
+    let input = if input.match_indices('\r').any(|(i, _)| lone_cr(i)) {
+        let lines = input
+            .char_indices()
+            .map(|(i, c)| if lone_cr(i) { '\n' } else { c });
+        Cow::Owned(lines.collect())
+    } else {
+        Cow::Borrowed(input)
+    };
+
+    match options.table_delimiter() {
+        Some(delimiter) => Cow::Owned(replace_table_delimiter(&input, delimiter)),
+        None => input,
+    }
+}
+
+/// Replaces `delimiter` by `|` in each line that starts and ends with it where a table may
+/// be: after a step or `Examples:` line, or another table row, with only blank and comment
+/// lines in between.
+fn replace_table_delimiter(input: &str, delimiter: char) -> String {
+    let tokens = token_scan(input);
+    let mut table_allowed = false;
+
+    let lines = input.split('\n').enumerate().map(|(i, line)| {
+        let row = line.trim_start_matches(' ').trim_end_matches([' ', '\r']);
+        let is_row = row.len() > 1 && row.starts_with(delimiter) && row.ends_with(delimiter);
+
+        match tokens.get(i).map_or(TokenKind::Eof, |t| t.kind) {
+            TokenKind::StepLine | TokenKind::ExamplesLine => table_allowed = true,
+            TokenKind::Empty | TokenKind::Comment | TokenKind::TableRow => {}
+            TokenKind::Other if table_allowed && is_row => {
+                return Cow::Owned(line.replace(delimiter, "|"));
+            }
+            _ => table_allowed = false,
+        }
+        Cow::Borrowed(line)
+    });
+
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+fn tag_names(tags: &[Tag]) -> Vec<String> {
+    tags.iter().map(|t| t.name.clone()).collect()
 }
 
 impl Default for GherkinEnv {
     fn default() -> Self {
-        GherkinEnv {
-            keywords: RefCell::new(DEFAULT_KEYWORDS),
-            last_step: RefCell::new(None),
-            last_keyword: RefCell::new(None),
-            line_offsets: RefCell::new(vec![0]),
-        }
+        GherkinEnv::new("")
     }
 }
 
 peg::parser! { pub(crate) grammar gherkin_parser(env: &GherkinEnv) for str {
 
-rule _() = quiet!{[' ']*}
-rule __() = quiet!{[' ']+}
+rule _() = quiet!{[' ' | '\t']*}
+rule __() = quiet!{[' ' | '\t']+}
 
 rule nl0() = quiet!{"\r"? "\n"}
-rule nl() = quiet!{nl0() p:position!() comment()* {
-    env.increment_nl(p);
-}} 
+rule nl() = quiet!{nl0() comment()*}
 rule eof() = quiet!{![_]}
-rule nl_eof() = quiet!{(nl() / [' '])+ / eof()}
-rule comment() = quiet!{[' ']* "#" $((!nl0()[_])*) nl()}
+rule nl_eof() = quiet!{(nl() / [' ' | '\t'])+ / eof()}
+rule comment() = quiet!{[' ' | '\t']* "#" $((!nl0()[_])*) nl() { env.hit("comment") }}
 rule not_nl() -> &'input str = n:$((!nl0()[_])+) { n }
 
-rule keyword1(list: &[&'static str]) -> &'static str
+rule keyword1(list: &[&'static str], ignore_case: bool) -> &'static str
     = input:$([_]*<
-        {list.iter().map(|x| x.len()).min().unwrap()},
-        {list.iter().map(|x| x.len()).max().unwrap()}
+        {list.iter().map(|x| x.chars().count()).min().unwrap()},
+        {list.iter().map(|x| x.chars().count()).max().unwrap()}
     >) {?
         // println!("Input: {} {:?}", &input, &list);
-        match list.iter().find(|x| input.starts_with(**x)) {
+        match find_keyword(list, input, ignore_case) {
             Some(v) => {
-                env.set_keyword((*v).to_string());
+                env.set_keyword(v.to_string());
                 // println!("Found: {}", &v);
                 Err("success")
             },
@@ -207,139 +541,277 @@ rule keyword1(list: &[&'static str]) -> &'static str
         }
     }
 
-rule keyword0(list: &[&'static str]) -> usize
-    = keyword1(list)? {?
+rule keyword0(list: &[&'static str], ignore_case: bool) -> usize
+    = keyword1(list, ignore_case)? {?
         match env.last_keyword().as_ref() {
-            Some(v) => Ok(v.len()),
+            Some(v) => Ok(v.chars().count()),
             None => Err("no match")
         }
     }
 
-pub(crate) rule keyword(list: &[&'static str]) -> &'static str
-    = len:keyword0(list) [_]*<{len}> {
+rule keyword_with_case(list: &[&'static str], ignore_case: bool) -> &'static str
+    = len:keyword0(list, ignore_case) [_]*<{len}> {
         let kw = env.take_keyword();
         list.iter().find(|x| **x == &*kw).unwrap()
     }
 
+pub(crate) rule keyword(list: &[&'static str]) -> &'static str
+    = k:keyword_with_case(list, false) { k }
+
+/// The colon after a block keyword, which may be full-width as in `Feature：`.
+rule colon() = [':' | '：']
+
 rule language_directive() -> ()
     = "# language: " l:$(['a'..='z']+) _ nl() {?
-        env.set_language(l)
+        env.set_language(l).map(|()| env.hit("language_directive"))
     }
 
-rule docstring() -> String
-    = "\"\"\"" n:$((!"\"\"\""[_])*) "\"\"\"" nl_eof() {
-        textwrap::dedent(n)
+/// A docstring, its delimiter and the `(start, end)` offset of it from the opening delimiter to
+/// the closing one, or to the end of its last line of text if it was never closed.
+rule docstring() -> (String, DocstringDelimiter, (usize, usize))
+    = pa:position!() d:docstring_delimiter() c:({ env.closing_fence_follows(pa, d) })
+      n:$((!fence(d) !(nl0() docstring_overrun((env.indent_width(pa)), c))[_])*) fence(d)
+      pb:position!() nl_eof() {
+        env.hit(match d {
+            DocstringDelimiter::Quotes => "docstring",
+            DocstringDelimiter::Backticks => "backtick_docstring",
+        });
+        (docstring_text(n, env.indent_width(pa), d), d, (pa, pb))
+    }
+    / pa:position!() d:docstring_delimiter()
+      n:$((!(nl0() docstring_stop((env.indent_width(pa))))[_])*)
+      e:(&eof() { true } / { false }) nl_eof() pb:position!() {?
+        if env.strict() {
+            env.pinned_error.set(Some((pa, match d {
+                DocstringDelimiter::Quotes => "closing \"\"\" of the docstring opened here",
+                DocstringDelimiter::Backticks => "closing ``` of the docstring opened here",
+            })));
+            Err(match d {
+                DocstringDelimiter::Quotes => "closing \"\"\"",
+                DocstringDelimiter::Backticks => "closing ```",
+            })
+        } else {
+            let end = if !e {
+                format!("ends before line {}", env.position(pb).0)
+            } else {
+                "ends at the end of the file".to_string()
+            };
+            env.warn("unclosed-docstring", &format!("docstring was never closed and {}", end), (pa, pa + 3));
+            env.hit("unclosed_docstring");
+            let end = pa + d.as_str().len() + n.trim_end().len();
+            Ok((docstring_text(n, env.indent_width(pa), d), d, (pa, end)))
+        }
     }
 
-rule table_cell() -> &'input str
-    = "|" _ !(nl0() / eof()) n:$((!"|"[_])*) { n }
+rule docstring_delimiter() -> DocstringDelimiter
+    = "\"\"\"" { DocstringDelimiter::Quotes }
+    / "```" { DocstringDelimiter::Backticks }
 
-pub(crate) rule table_row() -> Vec<String>
-    = n:(table_cell() ** _) _ "|" _ nl_eof() {
-        n.into_iter()
-            .map(str::trim)
-            .map(str::to_string)
-            .collect()
+/// The closing delimiter of a docstring opened with `delimiter`.
+rule fence(delimiter: DocstringDelimiter)
+    = d:docstring_delimiter() {? if d == delimiter { Ok(()) } else { Err(delimiter.as_str()) } }
+
+/// The start of a line that can only begin a new block.
+rule block_start()
+    = _ ("@" / keyword((&*env.keywords().blocks())) _ colon())
+
+/// The start of a line beginning a new block or a step.
+rule structural_line()
+    = block_start() / _ step_keyword() [' ' | '\t']
+
+/// The start of a line that an unclosed docstring opened `indent` columns in stops before: a
+/// new block, or a step, indented no further than the docstring.
+rule docstring_stop(indent: usize)
+    = pa:position!() structural_line() {?
+        if env.indent_width(pa) <= indent { Ok(()) } else { Err("docstring content") }
     }
 
-pub(crate) rule table0() -> Vec<Vec<String>>
-    = _ d:(table_row() ++ _) {
-        if d.is_empty() {
-            d
+/// When permissive, the start of a line that the content of a docstring opened `indent` columns
+/// in cannot reach: a new block, or a step, indented less than the docstring. Unless the
+/// docstring is `closed` by a delimiter that no later docstring pairs with, a delimiter past
+/// it belongs to another docstring, so this one was never closed.
+rule docstring_overrun(indent: usize, closed: bool)
+    = pa:position!() structural_line() {?
+        if !env.strict() && !closed && env.indent_width(pa) < indent {
+            env.hit("docstring_overrun");
+            Ok(())
         } else {
-            let len = d[0].len();
-            d.into_iter().map(|mut x| { x.truncate(len); x }).collect()
+            Err("docstring content")
         }
     }
 
-pub(crate) rule table() -> Table
-    = pa:position!() t:table0() pb:position!() {
-        Table::builder()
-            .span((pa, pb))
-            .position(env.position(pa))
-            .rows(t)
-            .build()
+/// Fails at the `chars`th character of the input with `expected`.
+pub(crate) rule error_at(chars: usize, expected: &'static str)
+    = [_]*<{chars}> {? Err(expected) }
+
+/// Parses `r`, forgetting the problems recorded and rules hit while parsing it if it fails.
+/// Both are recorded as soon as they are found, so this keeps those in input that is
+/// backtracked over from being reported.
+rule committed<T>(r: rule<T>) -> T
+    = m:mark() x:(x:r() { x } / quiet!{backtracked(m)}) { x }
+
+rule mark() -> (usize, usize) = { env.mark() }
+
+rule backtracked<T>(mark: (usize, usize)) -> T
+    = {? env.backtrack(mark); Err("backtracked") }
+
+rule table_cell() -> &'input str
+    = "|" _ !(nl0() / eof()) n:$(("\\" !nl0() [_] / !("|" / nl0())[_])*) &"|" { n }
+
+/// A comment after the closing `|` of a table row.
+rule row_comment() -> &'input str
+    = "#" c:$((!nl0()[_])*) {
+        env.hit("row_comment");
+        c.trim()
     }
 
-pub(crate) rule step() -> Step
-    = pa:position!() k:keyword((env.keywords().given)) __ n:not_nl() pb:position!() _ nl_eof() _
-      d:docstring()? t:table()?
-    {
-        env.set_last_step(StepType::Given);
-        Step::builder().ty(StepType::Given)
-            .raw_type(k.to_string())
-            .value(n.to_string())
-            .table(t)
-            .docstring(d)
-            .span((pa, pb))
-            .position(env.position(pa))
-            .build()
+pub(crate) rule table_row() -> (Vec<String>, Option<String>)
+    = n:(table_cell() ** _) _ "|" _ c:row_comment()? nl_eof() {
+        let cells = n.into_iter()
+            .map(str::trim)
+            .map(unescape_cell)
+            .collect();
+        (cells, c.map(str::to_string))
     }
-    / pa:position!() k:keyword((env.keywords().when)) __ n:not_nl() pb:position!() _ nl_eof() _
-      d:docstring()? t:table()?
-    {
-        env.set_last_step(StepType::When);
-        Step::builder().ty(StepType::When)
-            .raw_type(k.to_string())
-            .value(n.to_string())
-            .table(t)
-            .docstring(d)
-            .span((pa, pb))
-            .position(env.position(pa))
-            .build()
+
+rule positioned_table_row() -> TableRow
+    = pa:position!() r:table_row() {
+        TableRow {
+            position: env.position(pa),
+            cells: r.0,
+            comment: r.1,
+            leading_comments: env.comments_above(pa),
+        }
     }
-    / pa:position!() k:keyword((env.keywords().then)) __ n:not_nl() pb:position!() _ nl_eof() _
-      d:docstring()? t:table()?
-    {
-        env.set_last_step(StepType::Then);
-        Step::builder().ty(StepType::Then)
-            .raw_type(k.to_string())
-            .value(n.to_string())
-            .table(t)
-            .docstring(d)
+
+/// A line of a table that is not closed by `|` at both ends, such as `| 2 | oops` or `a | b |`.
+rule broken_table_row()
+    = pa:position!() (unpiped_start() / "|") (!nl0()[_])* pb:position!() nl_eof() {?
+        if env.strict() {
+            Err("table row")
+        } else {
+            env.warn("invalid-table-row", "table row has text outside its cells and was skipped", (pa, pb));
+            env.hit("broken_table_row");
+            Ok(())
+        }
+    }
+
+/// Text before the first `|` of a line that is not otherwise part of a step.
+rule unpiped_start()
+    = !("@" / "#" / docstring_delimiter() / any_directive() / step_keyword()) (!("|" / nl0())[_])+ "|"
+
+rule table_line() -> Option<TableRow>
+    = r:positioned_table_row() { Some(r) }
+    / broken_table_row() { None }
+
+rule table0() -> Vec<TableRow>
+    = _ d:(table_line() ++ _) {?
+        let d: Vec<_> = d.into_iter().flatten().collect();
+        match d.first().map(|row| row.cells.len()) {
+            None => Err("table row"),
+            Some(len) if env.strict() && d.iter().any(|row| row.cells.len() != len) => {
+                Err("rows with the same number of cells")
+            }
+            Some(len) => Ok(d.into_iter().map(|mut row| { row.cells.truncate(len); row }).collect()),
+        }
+    }
+
+pub(crate) rule table() -> Table
+    = pa:position!() t:table0() pb:position!() {?
+        let mut table = Table::builder()
             .span((pa, pb))
             .position(env.position(pa))
-            .build()
+            .rows(vec![])
+            .build();
+        for row in t {
+            table.rows.push(row.cells);
+            table.row_positions.push(row.position);
+            table.row_comments.push(row.comment);
+            table.row_leading_comments.push(row.leading_comments);
+        }
+        env.check_table_width(&table).map(|()| {
+            env.hit("table");
+            table
+        })
     }
-    / pa:position!() k:keyword((env.keywords().and)) __ n:not_nl() pb:position!() _ nl_eof() _
-      d:docstring()? t:table()?
-    {?
-        match env.last_step() {
-            Some(v) => {
-                Ok(Step::builder().ty(v)
-                    .raw_type(k.to_string())
-                    .value(n.to_string())
-                    .table(t)
-                    .docstring(d)
-                    .span((pa, pb))
-                    .position(env.position(pa))
-                    .build())
-            }
-            None => {
-                Err("given, when or then")
-            }
+
+rule step_keyword_in(list: &[&'static str]) -> &'input str
+    = k:$(keyword_with_case(list, (env.options.case_insensitive_keywords()))) {
+        if env.options.normalize_keyword_case() {
+            find_keyword(list, k, true).unwrap()
+        } else {
+            k
         }
     }
-    / pa:position!() k:keyword((env.keywords().but)) __ n:not_nl() pb:position!() _ nl_eof() _
-      d:docstring()? t:table()?
+
+rule step_keyword() -> (Option<StepType>, &'input str)
+    = k:step_keyword_in((env.keywords().given)) { (Some(StepType::Given), k) }
+    / k:step_keyword_in((env.keywords().when)) { (Some(StepType::When), k) }
+    / k:step_keyword_in((env.keywords().then)) { (Some(StepType::Then), k) }
+    / k:step_keyword_in((env.keywords().and)) { (None, k) }
+    / k:step_keyword_in((env.keywords().but)) { (None, k) }
+
+/// With [`ParseOptions::with_unknown_keyword_as_star`], the first word of a line that is not
+/// otherwise structural, read as the keyword of a step continuing the previous one.
+rule unknown_step_keyword() -> &'input str
+    = unknown_keywords_allowed() !block_start() !['|' | '#' | '"' | '`']
+      k:$((!([' ' | '\t'] / nl0()) [_])+) { k }
+
+rule unknown_keywords_allowed()
+    = {? if env.options.unknown_keyword_as_star() { Ok(()) } else { Err("step keyword") } }
+
+pub(crate) rule step() -> Step
+    = committed(<step_body()>)
+
+rule step_body() -> Step
+    = pa:position!() k:(step_keyword() / k:unknown_step_keyword() { (None, k) })
+      __ n:not_nl() pb:position!() _ nl_eof() _
+      d:docstring()? t:table()? u:sub_steps((env.indent_width(pa)), (k.0))?
     {?
-        match env.last_step() {
-            Some(v) => {
-                Ok(Step::builder().ty(v)
-                    .raw_type(k.to_string())
+        let (docstring, delimiter, docstring_span) =
+            d.map_or((None, Default::default(), None), |(d, x, span)| (Some(d), x, Some(span)));
+        match k.0.or_else(|| env.last_step()) {
+            Some(ty) => {
+                env.hit("step");
+                if k.0.is_none() {
+                    env.hit("continuation_step");
+                }
+                env.set_last_step(ty);
+                Ok(Step::builder().ty(ty)
+                    .raw_type(k.1.to_string())
                     .value(n.to_string())
                     .table(t)
-                    .docstring(d)
+                    .sub_steps(u.unwrap_or_default())
+                    .docstring(docstring)
+                    .docstring_delimiter(delimiter)
+                    .docstring_span(docstring_span)
                     .span((pa, pb))
                     .position(env.position(pa))
+                    .leading_blank_lines(env.leading_blank_lines(pa))
                     .build())
             }
-            None => {
-                Err("given, when or then")
-            }
+            None => Err("given, when or then"),
         }
     }
 
+/// With [`ParseOptions::with_sub_steps`], the `*` steps following a step of type `ty`, or
+/// continuing the one before, indented more than its `indent`.
+rule sub_steps(indent: usize, ty: Option<StepType>) -> Vec<Step>
+    = sub_steps_allowed() continue_step(ty) s:sub_step(indent)+ { s }
+
+rule sub_steps_allowed()
+    = {? if env.options.sub_steps() { Ok(()) } else { Err("step") } }
+
+/// Makes the steps that follow continue a step of type `ty`.
+rule continue_step(ty: Option<StepType>)
+    = { if let Some(ty) = ty { env.set_last_step(ty) } }
+
+rule sub_step(indent: usize) -> Step
+    = _ indented_past(indent) &("*" [' ' | '\t']) s:step() { s }
+
+rule indented_past(indent: usize)
+    = pa:position!() {? if env.indent_width(pa) > indent { Ok(()) } else { Err("step") } }
+
 pub(crate) rule steps() -> Vec<Step>
     = s:(step() ** _) {
         env.clear_last_step();
@@ -348,95 +820,154 @@ pub(crate) rule steps() -> Vec<Step>
 
 rule background() -> Background
     = _ pa:position!()
-      keyword((env.keywords().background)) ":" _ nl_eof()
+      keyword((env.keywords().background)) colon() _ nl_eof()
+      d:background_description()?
       s:steps()?
       pb:position!()
     {
+        env.recovered_past(pb);
+        env.hit("background");
         Background::builder()
-            .steps(s.unwrap_or_else(|| vec![]))
+            .steps(s.unwrap_or_default())
+            .description(d.flatten())
             .span((pa, pb))
             .position(env.position(pa))
+            .leading_blank_lines(env.leading_blank_lines(pa))
             .build()
     }
 
+/// A file holding only a background, as named by an `# include:` comment.
+pub(crate) rule background_snippet() -> Background
+    = comment()* nl()* b:background() nl()* { b }
+
 rule any_directive() -> &'static str
     = k:keyword((&*env.keywords().all())) {
         // println!("Found directive: {}", &k);
         k
     }
 
+/// The start of the first line after a description: a block keyword and its colon, or a line
+/// of tags leading to one. Other lines, however they begin, are part of the description.
+rule description_end()
+    = keyword((&*env.keywords().blocks())) _ colon()
+    / tags() (_ nl0())* _ keyword((&*env.keywords().blocks())) _ colon()
+
 rule description_line() -> &'input str
-    = _ !"@" !any_directive() _ n:not_nl() nl_eof() { n }
+    = _ !description_end() n:not_nl() nl_eof() { n }
 
 rule description() -> Option<String>
     = d:(description_line() ** _) {
-        let d = d.join("\n");
-        if d.trim() == "" {
-            None
-        } else {
-            Some(d)
+        if !d.is_empty() {
+            env.hit("description");
+        }
+        env.description(d)
+    }
+
+/// A line of a background's description, which also ends at its first step.
+rule background_description_line() -> &'input str
+    = _ !description_end() !(step_keyword() [' ' | '\t']) n:not_nl() nl_eof() { n }
+
+rule background_description() -> Option<String>
+    = d:(background_description_line() ** _) {
+        if !d.is_empty() {
+            env.hit("description");
         }
+        env.description(d)
+    }
+
+/// A docstring where the table of an examples block belongs, which is always an error.
+rule examples_docstring()
+    = _ pa:position!() docstring_delimiter() {
+        env.pinned_error.set(Some((pa, "examples table rather than a docstring")));
+        env.hit("examples_docstring");
     }
 
 rule examples() -> Examples
     = _
+      ta:position!()
       t:tags()
       _
       pa:position!()
-      keyword((env.keywords().examples)) ":" _ nl_eof()
-      tb:table()
+      keyword((env.keywords().examples)) colon() _ nl_eof()
+      !examples_docstring()
+      tb:table()?
       pb:position!()
     {
+        env.hit("examples");
+        if !t.is_empty() {
+            env.hit("examples_tags");
+        }
         Examples::builder()
-            .tags(t)
+            .tags(tag_names(&t))
+            .tag_tokens(t)
             .table(tb)
             .span((pa, pb))
             .position(env.position(pa))
+            .leading_blank_lines(env.leading_blank_lines(ta))
             .build()
     }
 
 rule scenario() -> Scenario
     = _
+      ta:position!()
       t:tags()
       _
       pa:position!()
-      keyword((env.keywords().scenario)) ":" _ n:not_nl() _ nl_eof()
+      keyword((env.keywords().scenario)) colon() _ n:not_nl() _ nl_eof()
       s:steps()?
-      e:examples()?
+      e:examples()*
       pb:position!()
     {
+        env.recovered_past(pb);
+        env.warn_dedented_examples(pa, n, &e);
+        env.hit("scenario");
+        if !t.is_empty() {
+            env.hit("scenario_tags");
+        }
         Scenario::builder()
             .name(n.to_string())
-            .tags(t)
-            .steps(s.unwrap_or_else(|| vec![]))
+            .tags(tag_names(&t))
+            .tag_tokens(t)
+            .steps(s.unwrap_or_default())
             .examples(e)
             .span((pa, pb))
             .position(env.position(pa))
+            .leading_blank_lines(env.leading_blank_lines(ta))
             .build()
     }
     / _
+      ta:position!()
       t:tags()
       _
       pa:position!()
-      keyword((env.keywords().scenario_outline)) ":" _ n:not_nl() _ nl_eof()
+      keyword((env.keywords().scenario_outline)) colon() _ n:not_nl() _ nl_eof()
       s:steps()?
-      e:examples()?
+      e:examples()*
       pb:position!()
     {
+        env.recovered_past(pb);
+        env.warn_dedented_examples(pa, n, &e);
+        env.hit("scenario_outline");
+        if !t.is_empty() {
+            env.hit("scenario_tags");
+        }
         Scenario::builder()
             .name(n.to_string())
-            .tags(t)
-            .steps(s.unwrap_or_else(|| vec![]))
+            .tags(tag_names(&t))
+            .tag_tokens(t)
+            .steps(s.unwrap_or_default())
             .examples(e)
             .span((pa, pb))
             .position(env.position(pa))
+            .leading_blank_lines(env.leading_blank_lines(ta))
+            .outline(true)
             .build()
     }
 
 rule tag_char() -> &'input str
     = s:$([_]) {?
         let x = s.chars().next().unwrap();
-        if x.is_alphanumeric() || x == '_' || x == '-' {
+        if x.is_alphanumeric() || x == '_' || x == '-' || x == ':' {
             Ok(s)
         } else {
             Err("tag character")
@@ -446,48 +977,119 @@ rule tag_char() -> &'input str
 pub(crate) rule tag() -> String
     = "@" s:tag_char()+ { s.join("") }
 
-pub(crate) rule tags() -> Vec<String>
-    = t:(tag() ** ([' ']+)) _ nl() { t }
+rule tag_token() -> Tag
+    = pa:position!() t:tag() pb:position!() {
+        Tag::builder()
+            .name(t)
+            .span((pa, pb))
+            .position(env.position(pa))
+            .build()
+    }
+
+pub(crate) rule tags() -> Vec<Tag>
+    = t:(tag_token() ** __) _ nl() { t }
     / { vec![] }
 
 rule rule_() -> Rule
+    = committed(<rule_body()>)
+
+rule rule_body() -> Rule
     = _
+      ta:position!()
       t:tags()
       _
       pa:position!()
-      keyword((env.keywords().rule)) ":" _ n:not_nl() _ nl_eof()
-      s:scenarios()?
+      keyword((env.keywords().rule)) colon() _ n:not_nl() _ nl_eof()
+      d:description()?
+      c:rule_children()
     //   e:examples()?
       pb:position!()
     {
+        env.recovered_past(pb);
+        env.hit("rule");
+        if !t.is_empty() {
+            env.hit("rule_tags");
+        }
         Rule::builder()
             .name(n.to_string())
-            .tags(t)
-            .scenarios(s.unwrap_or_else(|| vec![]))
+            .description(d.flatten())
+            .tags(tag_names(&t))
+            .tag_tokens(t)
+            .children(c)
             .span((pa, pb))
             .position(env.position(pa))
+            .leading_blank_lines(env.leading_blank_lines(ta))
             .build()
     }
 
+rule rule_child() -> RuleChild
+    = b:background() {
+        env.hit("rule_background");
+        RuleChild::Background(b)
+    }
+    / s:scenario() { RuleChild::Scenario(s) }
+
+/// The background and scenarios of a rule. A second background is an error, as is, when
+/// strict, a background after the first scenario.
+rule rule_children() -> Vec<RuleChild>
+    = _ c:(rule_child() ** _)? {?
+        let c = c.unwrap_or_default();
+        let mut backgrounds = c.iter().enumerate().filter_map(|(i, child)| match child {
+            RuleChild::Background(b) => Some((i, b.span.0)),
+            RuleChild::Scenario(_) => None,
+        });
+        let first = backgrounds.next();
+        let error = match (first, backgrounds.next()) {
+            (_, Some((_, offset))) => Some((offset, "at most one background in a rule")),
+            (Some((i, offset)), None) if i > 0 && env.strict() => {
+                Some((offset, "background before the scenarios of the rule"))
+            }
+            _ => None,
+        };
+        match error {
+            Some(error) => {
+                env.pinned_error.set(Some(error));
+                Err(error.1)
+            }
+            None => Ok(c),
+        }
+    }
+
 rule rules() -> Vec<Rule>
-    = _ r:(rule_() ** _)? { r.unwrap_or_else(|| vec![]) }
+    = _ r:(rule_() ** _)? { r.unwrap_or_default() }
 
 pub(crate) rule scenarios() -> Vec<Scenario>
-    = _ s:(scenario() ** _)? { s.unwrap_or_else(|| vec![]) }
+    = _ s:(scenario() ** _)? { s.unwrap_or_default() }
+
+rule feature_name() -> &'input str
+    = n:not_nl() { n }
+    / nl()+ _ !"@" !any_directive() n:not_nl() {?
+        if env.options.title_on_next_line() {
+            Ok(n)
+        } else {
+            Err("feature name")
+        }
+    }
+    / { "" }
 
 pub rule feature() -> Feature
-    = _ language_directive()? nl()*
+    = _ language_directive()? comment()* nl()*
       t:tags() nl()*
       pa:position!()
-      keyword((env.keywords().feature)) ":" _ n:not_nl() _ nl()+
+      keyword((env.keywords().feature)) colon() _ n:feature_name() _ nl()+
       d:description()? nl()*
       b:background()? nl()*
       s:scenarios() nl()*
       r:rules() pb:position!()
       nl()*
     {
+        env.hit("feature");
+        if !t.is_empty() {
+            env.hit("feature_tags");
+        }
         Feature::builder()
-            .tags(t)
+            .tags(tag_names(&t))
+            .tag_tokens(t)
             .name(n.to_string())
             .description(d.flatten())
             .background(b)
@@ -495,6 +1097,7 @@ pub rule feature() -> Feature
             .rules(r)
             .span((pa, pb))
             .position(env.position(pa))
+            .trailing_newline(env.trailing_newline)
             .build()
     }
 
@@ -536,15 +1139,303 @@ Evidence: A gubbins in an airlock
 ";
     #[test]
     fn smoke() {
-        let env = GherkinEnv::default();
+        let env = GherkinEnv::new(FOO);
         assert!(gherkin_parser::feature(FOO, &env).is_ok());
     }
 
     #[test]
     fn smoke2() {
-        let env = GherkinEnv::default();
         let d = env!("CARGO_MANIFEST_DIR");
         let s = std::fs::read_to_string(format!("{}/tests/test.feature", d)).unwrap();
+        let env = GherkinEnv::new(&s);
         assert!(gherkin_parser::feature(&s, &env).is_ok());
     }
+
+    #[test]
+    fn tag_positions_match_the_tag_line() {
+        let input = "Feature: Tags\n\n  @lol @a\t@rule     @with-spaces\n  Scenario: Tagged\n";
+        let env = GherkinEnv::new(input);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        let scenario = &feature.scenarios[0];
+
+        assert_eq!(scenario.tags, vec!["lol", "a", "rule", "with-spaces"]);
+        let positions: Vec<_> = scenario.tag_tokens.iter().map(|t| t.position).collect();
+        assert_eq!(positions, vec![(3, 3), (3, 8), (3, 11), (3, 21)]);
+        for (tag, name) in scenario.tag_tokens.iter().zip(&scenario.tags) {
+            assert_eq!(&input[tag.span.0..tag.span.1], format!("@{}", name));
+        }
+    }
+
+    #[test]
+    fn comments_between_table_rows_do_not_end_the_table() {
+        let input = "Feature: T\nScenario: A\n  Given a table\n    | a | b |\n    | 1 | 2 |\n    # note | x |\n    | 3 | 4 |\n  Then it works\n";
+        let env =
+            GherkinEnv::with_options(input, ParseOptions::default().with_mode(ParseMode::Strict));
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        let steps = &feature.scenarios[0].steps;
+
+        let table = steps[0].table.as_ref().unwrap();
+        assert_eq!(
+            table.rows,
+            vec![vec!["a", "b"], vec!["1", "2"], vec!["3", "4"]]
+        );
+        assert_eq!(table.row_positions, vec![(4, 5), (5, 5), (7, 5)]);
+        assert_eq!(table.row_leading_comments[2], vec!["note | x |"]);
+        assert_eq!(steps[1].value, "it works");
+        assert!(env.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn full_width_colons_follow_keywords() {
+        let input =
+            "Feature： Wide\n  Background：\n    Given a\n  Scenario：Colons\n    Given b\n";
+        let env = GherkinEnv::new(input);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        assert_eq!(feature.name, "Wide");
+        assert_eq!(feature.background.unwrap().steps.len(), 1);
+        assert_eq!(feature.scenarios[0].name, "Colons");
+    }
+
+    #[test]
+    fn step_value_keeps_backslash_n_literal() {
+        let input = "Feature: Paths\nScenario: A Windows path\n  Given a path C:\\new\n";
+        let env = GherkinEnv::new(input);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        assert_eq!(feature.scenarios[0].steps[0].value, "a path C:\\new");
+    }
+
+    #[test]
+    fn smart_quotes_are_not_docstring_delimiters() {
+        let input = "Feature: Quotes
+Scenario: Curly quotes
+  Given the title “““Quoted””” and it’s ‘fine’
+  Then nothing else happens
+";
+        let env = GherkinEnv::new(input);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        let step = &feature.scenarios[0].steps[0];
+        assert_eq!(step.value, "the title “““Quoted””” and it’s ‘fine’");
+        assert_eq!(step.docstring, None);
+        assert_eq!(feature.scenarios[0].steps.len(), 2);
+    }
+
+    #[test]
+    fn positions_survive_backtracking_and_missing_final_newline() {
+        let input = "Feature: A\n\nScenario: B\n  Given x\n\n@t\nScenario: C\n  When z\n  Then w";
+        let env = GherkinEnv::new(input);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        assert_eq!(feature.scenarios[1].position, (7, 1));
+        assert_eq!(feature.scenarios[1].steps[1].position, (9, 3));
+        assert_eq!(feature.scenarios[1].leading_blank_lines, 1);
+    }
+
+    #[test]
+    fn examples_after_blank_lines_and_comments() {
+        let input = r"Feature: Outlines
+  Scenario Outline: Eating
+    Given there are <start> cucumbers
+    # the table is below
+
+    # after a blank line
+
+    Examples:
+      | start |
+      | 12    |
+
+  Scenario: Next
+    Given nothing
+";
+        let env = GherkinEnv::new(input);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        assert_eq!(feature.scenarios.len(), 2);
+        let examples = &feature.scenarios[0].examples[0];
+        assert_eq!(
+            examples.table.as_ref().unwrap().rows,
+            vec![vec!["start"], vec!["12"]]
+        );
+        assert_eq!(examples.position, (8, 5));
+    }
+
+    #[test]
+    fn docstring_indentation_is_relative_to_the_delimiter() {
+        let input = "Feature: Docstrings
+  Scenario: Tabs inside spaces
+    Given some text
+    \"\"\"
+    first
+\t  tabbed
+      nested
+    \"\"\"
+\tScenario: Spaces inside tabs
+\t\tGiven some text
+\t\t\"\"\"
+\t\tfirst
+\t\t    nested
+\t\t\tdeeper
+\t\t\"\"\"
+";
+        let env = GherkinEnv::new(input);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        assert_eq!(
+            feature.scenarios[0].steps[0].docstring.as_deref(),
+            Some("\nfirst\n      tabbed\n  nested\n")
+        );
+        assert_eq!(
+            feature.scenarios[1].steps[0].docstring.as_deref(),
+            Some("\nfirst\n    nested\n\tdeeper\n")
+        );
+    }
+
+    #[test]
+    fn problems_backtracked_over_are_forgotten() {
+        // The table's broken row is found before the step fails for want of a type.
+        let input = "And a step\n  | a |\n  | b | oops\n";
+        let env = GherkinEnv::new(input);
+        assert!(gherkin_parser::step(input, &env).is_err());
+        assert_eq!(env.diagnostics(), vec![]);
+
+        let input = "Given a step\n  | a |\n  | b | oops\n";
+        let env = GherkinEnv::new(input);
+        assert!(gherkin_parser::step(input, &env).is_ok());
+        assert_eq!(env.diagnostics()[0].code, "invalid-table-row");
+    }
+
+    #[test]
+    fn bullets_can_be_read_as_sub_steps() {
+        let input = "Feature: Checklists
+  Scenario: Packing
+    Given a suitcase
+    When I pack
+      * a toothbrush
+      * some socks
+    * and close it
+    Then it is full
+";
+        let options = ParseOptions::default().with_sub_steps(true);
+        let env = GherkinEnv::with_options(input, options);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        let steps = &feature.scenarios[0].steps;
+
+        assert_eq!(steps.len(), 4);
+        assert_eq!(
+            steps[1]
+                .sub_steps
+                .iter()
+                .map(|s| (s.ty, s.value.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (StepType::When, "a toothbrush"),
+                (StepType::When, "some socks")
+            ]
+        );
+        assert_eq!(steps[1].sub_steps[1].position, (6, 7));
+        assert_eq!(steps[2].value, "and close it");
+        assert!(steps[2].sub_steps.is_empty());
+
+        let env = GherkinEnv::new(input);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        assert_eq!(feature.scenarios[0].steps.len(), 6);
+    }
+
+    #[test]
+    fn step_keywords_may_start_scenario_names() {
+        let input = "Feature: Names
+  Scenario: Given enough time, it works
+    When time passes
+    Then it works
+";
+        let env = GherkinEnv::new(input);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        let scenario = &feature.scenarios[0];
+        assert_eq!(scenario.name, "Given enough time, it works");
+        assert_eq!(scenario.steps.len(), 2);
+        assert_eq!(scenario.steps[0].ty, StepType::When);
+        assert_eq!(scenario.steps[0].value, "time passes");
+    }
+
+    #[test]
+    fn blank_lines_between_steps_do_not_end_the_scenario() {
+        let input = "Feature: Spacing
+  Scenario: Spread out
+    Given a step
+
+    When another follows a blank line
+
+
+    Then a third follows two
+	
+    And a fourth follows whitespace
+
+  Scenario: Next
+    Given more
+";
+        let env = GherkinEnv::new(input);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        assert_eq!(feature.scenarios.len(), 2);
+
+        let steps = &feature.scenarios[0].steps;
+        let values: Vec<_> = steps.iter().map(|s| &*s.value).collect();
+        assert_eq!(
+            values,
+            vec![
+                "a step",
+                "another follows a blank line",
+                "a third follows two",
+                "a fourth follows whitespace"
+            ]
+        );
+        assert_eq!(steps[3].position, (10, 5));
+    }
+
+    #[test]
+    fn docstrings_may_start_at_column_zero() {
+        let input = "Feature: Docstrings
+  Scenario: Flush left
+    Given some text
+\"\"\"
+first
+  indented
+\tand tabbed
+\"\"\"
+    Then the text is kept
+";
+        let env = GherkinEnv::new(input);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        let steps = &feature.scenarios[0].steps;
+        assert_eq!(
+            steps[0].docstring.as_deref(),
+            Some("\nfirst\n  indented\n\tand tabbed\n")
+        );
+        assert_eq!(steps[1].value, "the text is kept");
+    }
+
+    #[test]
+    fn unknown_keywords_can_be_read_as_star() {
+        let input = "Feature: Drafts
+  Scenario: Half written
+    Given a draft
+    Also a second draft
+    Then it is saved
+    Afterwards it is sent
+";
+        let options = ParseOptions::default().with_unknown_keyword_as_star(true);
+        let env = GherkinEnv::with_options(input, options);
+        let feature = gherkin_parser::feature(input, &env).unwrap();
+        let steps = &feature.scenarios[0].steps;
+        assert_eq!(
+            steps
+                .iter()
+                .map(|s| (s.ty, s.raw_type.as_str(), s.value.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (StepType::Given, "Given", "a draft"),
+                (StepType::Given, "Also", "a second draft"),
+                (StepType::Then, "Then", "it is saved"),
+                (StepType::Then, "Afterwards", "it is sent"),
+            ]
+        );
+
+        let env = GherkinEnv::new(input);
+        assert!(gherkin_parser::feature(input, &env).is_err());
+    }
 }
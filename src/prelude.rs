@@ -0,0 +1,22 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The core types and extension traits, for glob importing.
+//!
+//! ```
+//! use gherkin_rust::prelude::*;
+//!
+//! let feature = Feature::parse("Feature: A\n  Scenario: B\n    Given a step\n").unwrap();
+//! assert_eq!(feature.scenario_count(), 1);
+//! ```
+
+pub use crate::ext::{FeatureExt, ScenarioExt, StepExt, TableExt};
+pub use crate::{
+    Background, Examples, Feature, ParseMode, ParseOptions, ParsedFeature, Rule, Scenario, Step,
+    StepType, Table, Tag,
+};
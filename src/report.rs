@@ -0,0 +1,316 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::tagexpr::TagOperation;
+use crate::{Feature, FeatureChild, FeatureExt, Scenario, Tag};
+
+/// The kind of node a tag is written on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TagOwner {
+    Feature,
+    Rule,
+    Scenario,
+    Examples,
+}
+
+/// A place a tag is written in a .feature file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TagOccurrence<'a> {
+    /// The kind of node the tag is written on.
+    pub owner: TagOwner,
+    /// The path of the feature containing the tag, if known.
+    pub path: Option<&'a Path>,
+    /// The `(line, col)` position of the tag, or of its node if the tag has no recorded location.
+    pub position: (usize, usize),
+}
+
+/// Everywhere a tag is written, as collected by [`tag_report`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TagUsage<'a> {
+    /// The tag name, without the leading `@`.
+    pub tag: String,
+    /// Where the tag is written, in the order the features were given and then document order.
+    pub occurrences: Vec<TagOccurrence<'a>>,
+}
+
+/// Every tag written in `features`, sorted by name.
+///
+/// Only tags as written are counted, not those a scenario inherits from its feature or rule.
+/// With `ignore_case`, tags differing only in case are reported together under their
+/// lowercase name.
+pub fn tag_report(features: &[Feature], ignore_case: bool) -> Vec<TagUsage<'_>> {
+    let mut usages: BTreeMap<String, Vec<TagOccurrence<'_>>> = BTreeMap::new();
+
+    for feature in features {
+        for (owner, tags, tokens, position) in tagged_nodes(feature) {
+            for (i, tag) in tags.iter().enumerate() {
+                let tag = if ignore_case {
+                    tag.to_lowercase()
+                } else {
+                    tag.clone()
+                };
+                usages.entry(tag).or_default().push(TagOccurrence {
+                    owner,
+                    path: feature.path.as_deref(),
+                    position: tokens.get(i).map_or(position, |t| t.position),
+                });
+            }
+        }
+    }
+
+    usages
+        .into_iter()
+        .map(|(tag, occurrences)| TagUsage { tag, occurrences })
+        .collect()
+}
+
+/// Why a tag expression does or does not select a scenario, as found by
+/// [`Feature::explain_tags`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TagExplanation<'a> {
+    /// Whether the expression selects the scenario, or any of its examples blocks.
+    pub matched: bool,
+    /// Each tag named in the expression, in the order first written.
+    pub tags: Vec<TagMatch<'a>>,
+}
+
+/// A tag named in a tag expression, and where a scenario gets it from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TagMatch<'a> {
+    /// The tag name, without the leading `@`.
+    pub tag: String,
+    /// Where the tag is first written among the nodes the scenario takes tags from, or `None`
+    /// if it does not have the tag.
+    pub occurrence: Option<TagOccurrence<'a>>,
+}
+
+/// Renders the explanation over several lines, such as:
+///
+/// ```text
+/// not selected
+///   @smoke: scenario at 4:3
+///   @wip: feature at invoices.feature:1:1
+///   @slow: not found
+/// ```
+impl std::fmt::Display for TagExplanation<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            if self.matched {
+                "selected"
+            } else {
+                "not selected"
+            }
+        )?;
+
+        for tag in &self.tags {
+            write!(f, "\n  @{}: ", tag.tag)?;
+            match &tag.occurrence {
+                Some(o) => {
+                    let owner = format!("{:?}", o.owner).to_lowercase();
+                    write!(f, "{} at ", owner)?;
+                    if let Some(path) = o.path {
+                        write!(f, "{}:", path.display())?;
+                    }
+                    write!(f, "{}:{}", o.position.0, o.position.1)?;
+                }
+                None => write!(f, "not found")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Feature {
+    /// Explains whether `expression` selects `scenario`, which must be borrowed from this
+    /// feature, and where each tag it names comes from.
+    ///
+    /// The scenario's tags are those of the feature, its rule and the scenario itself. An
+    /// outline is selected if any examples block's tags added to those match, as with
+    /// [`filter_by_expression`](Feature::filter_by_expression).
+    pub fn explain_tags<'a>(
+        &'a self,
+        scenario: &'a Scenario,
+        expression: &TagOperation,
+    ) -> TagExplanation<'a> {
+        let mut nodes = vec![(
+            TagOwner::Feature,
+            &*self.tags,
+            &*self.tag_tokens,
+            self.position,
+        )];
+        if let Some(r) = self.rule_of(scenario) {
+            nodes.push((TagOwner::Rule, &r.tags, &r.tag_tokens, r.position));
+        }
+        nodes.extend(scenario_nodes(scenario));
+
+        let effective = self.effective_tags(scenario);
+        let matched = if scenario.examples.is_empty() {
+            expression.matches(&effective)
+        } else {
+            scenario.examples.iter().any(|e| {
+                let mut tags = effective.clone();
+                tags.extend(e.tags.iter().map(|t| &**t));
+                expression.matches(&tags)
+            })
+        };
+
+        let mut literals = vec![];
+        tag_literals(expression, &mut literals);
+
+        let tags = literals
+            .into_iter()
+            .map(|tag| TagMatch {
+                tag: tag.to_string(),
+                occurrence: nodes.iter().find_map(|(owner, tags, tokens, position)| {
+                    let i = tags.iter().position(|t| t == tag)?;
+                    Some(TagOccurrence {
+                        owner: *owner,
+                        path: self.path.as_deref(),
+                        position: tokens.get(i).map_or(*position, |t| t.position),
+                    })
+                }),
+            })
+            .collect();
+
+        TagExplanation { matched, tags }
+    }
+}
+
+fn tag_literals<'a>(expression: &'a TagOperation, out: &mut Vec<&'a str>) {
+    match expression {
+        TagOperation::And(a, b) | TagOperation::Or(a, b) => {
+            tag_literals(a, out);
+            tag_literals(b, out);
+        }
+        TagOperation::Not(a) => tag_literals(a, out),
+        TagOperation::Tag(t) => {
+            if !out.contains(&&**t) {
+                out.push(t);
+            }
+        }
+    }
+}
+
+type TaggedNode<'a> = (TagOwner, &'a [String], &'a [Tag], (usize, usize));
+
+/// The nodes of `feature` that can be tagged, in document order.
+fn tagged_nodes(feature: &Feature) -> Vec<TaggedNode<'_>> {
+    let mut nodes = vec![(
+        TagOwner::Feature,
+        &*feature.tags,
+        &*feature.tag_tokens,
+        feature.position,
+    )];
+    for child in feature.children() {
+        match child {
+            FeatureChild::Background(_) => {}
+            FeatureChild::Rule(r) => {
+                nodes.push((TagOwner::Rule, &r.tags, &r.tag_tokens, r.position));
+                nodes.extend(r.scenarios().flat_map(scenario_nodes));
+            }
+            FeatureChild::Scenario(s) => nodes.extend(scenario_nodes(s)),
+        }
+    }
+
+    nodes
+}
+
+fn scenario_nodes(s: &Scenario) -> impl Iterator<Item = TaggedNode<'_>> {
+    let examples = s
+        .examples
+        .iter()
+        .map(|e| (TagOwner::Examples, &*e.tags, &*e.tag_tokens, e.position));
+    std::iter::once((TagOwner::Scenario, &*s.tags, &*s.tag_tokens, s.position)).chain(examples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoices() -> Feature {
+        let mut feature = Feature::parse(
+            r"@Billing
+Feature: Invoices
+
+  @smoke
+  Scenario: Paying
+    Given an invoice
+
+  @billing
+  Rule: Refunds
+    Scenario: Refunding
+      Given a refund
+
+    @smoke
+    Scenario Outline: Partial refunds
+      Given <n> refunds
+
+      @billing
+      Examples:
+        | n |
+        | 1 |
+",
+        )
+        .unwrap();
+        feature.path = Some("invoices.feature".into());
+        feature
+    }
+
+    #[test]
+    fn reports_literal_tags() {
+        let first = invoices();
+        let second = Feature::parse("@smoke\nFeature: Login\n").unwrap();
+        let features = [first, second];
+
+        let report = tag_report(&features, false);
+        let tags: Vec<_> = report.iter().map(|u| &*u.tag).collect();
+        assert_eq!(tags, vec!["Billing", "billing", "smoke"]);
+
+        let smoke = &report[2].occurrences;
+        assert_eq!(smoke.len(), 3);
+        assert_eq!(smoke[0].owner, TagOwner::Scenario);
+        assert_eq!(smoke[0].position, (4, 3));
+        assert_eq!(smoke[0].path, Some(Path::new("invoices.feature")));
+        assert_eq!(smoke[2].owner, TagOwner::Feature);
+        assert_eq!(smoke[2].path, None);
+
+        let report = tag_report(&features, true);
+        let owners: Vec<_> = report[0].occurrences.iter().map(|o| o.owner).collect();
+        assert_eq!(report[0].tag, "billing");
+        assert_eq!(
+            owners,
+            vec![TagOwner::Feature, TagOwner::Rule, TagOwner::Examples]
+        );
+    }
+
+    #[test]
+    fn explains_tag_expressions() {
+        let features = [invoices()];
+
+        let refunding = features[0].rules[0].scenarios().nth(1).unwrap();
+        let expression = "@smoke and not @billing and not @slow".parse().unwrap();
+        let explanation = features[0].explain_tags(refunding, &expression);
+        assert!(!explanation.matched);
+        assert_eq!(
+            explanation.to_string(),
+            "not selected
+  @smoke: scenario at invoices.feature:13:5
+  @billing: rule at invoices.feature:8:3
+  @slow: not found"
+        );
+
+        let expression = "@smoke or @billing".parse().unwrap();
+        assert!(features[0].explain_tags(refunding, &expression).matched);
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt::Write;
+
+use crate::{LineCol, ParseError};
+
+/// Formats a parse error for display, such as for a terminal, a JSON log or an editor
+pub trait ErrorReporter {
+    /// Renders `err`, which was returned from parsing `source`.
+    fn report(&self, err: &ParseError<LineCol>, source: &str) -> String;
+}
+
+/// Renders parse errors in the style of rustc, quoting the offending line with a caret under
+/// the column the error was found at.
+///
+/// ```text
+/// error: expected closing """ of the docstring opened here
+///  --> 4:7
+///   |
+/// 4 |       """
+///   |       ^
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct RustcReporter;
+
+impl ErrorReporter for RustcReporter {
+    fn report(&self, err: &ParseError<LineCol>, source: &str) -> String {
+        let location = &err.location;
+        let offset = location.offset.min(source.len());
+        let start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = source[offset..]
+            .find('\n')
+            .map_or(source.len(), |i| offset + i);
+        let line = source[start..end].trim_end_matches('\r');
+
+        // Tabs are kept in the caret's indentation so it lines up however they are displayed.
+        let indent: String = source[start..offset]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let number = location.line.to_string();
+        let gutter = " ".repeat(number.len());
+
+        let mut out = String::new();
+        writeln!(out, "error: expected {}", err.expected).unwrap();
+        writeln!(out, "{}--> {}:{}", gutter, location.line, location.column).unwrap();
+        writeln!(out, "{} |", gutter).unwrap();
+        writeln!(out, "{} | {}", number, line).unwrap();
+        writeln!(out, "{} | {}^", gutter, indent).unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Feature, ParseMode, ParseOptions, ParseSourceError};
+
+    #[test]
+    fn rustc_style_report() {
+        let source = "Feature: Broken\n  Scenario: A\n    Given a step\n\t\"\"\"\n    unclosed\n";
+        let options = ParseOptions::default().with_mode(ParseMode::Strict);
+        let err = match Feature::parse_with_options(source.to_string(), &options) {
+            Err(ParseSourceError::Parsing(err)) => err,
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+
+        assert_eq!(
+            RustcReporter.report(&err, source),
+            "error: expected closing \"\"\" of the docstring opened here\n --> 4:2\n  |\n4 | \t\"\"\"\n  | \t^\n"
+        );
+    }
+}
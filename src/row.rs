@@ -0,0 +1,122 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// A data row of a [`Table`](crate::Table), addressable by the names in the table's header row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Row<'a> {
+    header: &'a [String],
+    cells: &'a [String],
+    line: usize,
+}
+
+/// A data row of an [`Examples`](crate::Examples) table.
+pub type ExampleRow<'a> = Row<'a>;
+
+/// An error converting a table cell to a typed value.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CellParseError {
+    #[error("line {line}: no column named {column:?}")]
+    MissingColumn { column: String, line: usize },
+
+    #[error("line {line}: could not parse {text:?} in column {column:?}: {reason}")]
+    Invalid {
+        column: String,
+        line: usize,
+        text: String,
+        reason: String,
+    },
+}
+
+impl<'a> Row<'a> {
+    pub(crate) fn new(header: &'a [String], cells: &'a [String], line: usize) -> Row<'a> {
+        Row {
+            header,
+            cells,
+            line,
+        }
+    }
+
+    /// The header row of the table this row belongs to.
+    pub fn header(&self) -> &'a [String] {
+        self.header
+    }
+
+    /// The cells of this row, in column order.
+    pub fn cells(&self) -> &'a [String] {
+        self.cells
+    }
+
+    /// The line this row was found on in the .feature file.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The cell in the named column, if the column exists.
+    pub fn get(&self, column: &str) -> Option<&'a str> {
+        self.header
+            .iter()
+            .position(|x| x == column)
+            .and_then(|i| self.cells.get(i))
+            .map(|x| &**x)
+    }
+
+    /// Parses the cell in the named column with `FromStr`.
+    pub fn get_parsed<T>(&self, column: &str) -> Result<T, CellParseError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let text = self.cell(column)?;
+        text.parse()
+            .map_err(|e: T::Err| self.invalid(column, text, e))
+    }
+
+    /// Parses the cell in the named column as an `i64`.
+    pub fn get_i64(&self, column: &str) -> Result<i64, CellParseError> {
+        self.get_parsed(column)
+    }
+
+    /// Parses the cell in the named column as an `f64`.
+    pub fn get_f64(&self, column: &str) -> Result<f64, CellParseError> {
+        self.get_parsed(column)
+    }
+
+    /// Parses the cell in the named column as a `bool`.
+    ///
+    /// `true`, `yes` and `1` are `true`; `false`, `no` and `0` are `false`. Matching ignores
+    /// ASCII case, so `Yes` and `TRUE` are accepted too. Anything else is an error.
+    pub fn get_bool(&self, column: &str) -> Result<bool, CellParseError> {
+        let text = self.cell(column)?;
+
+        match text.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "1" => Ok(true),
+            "false" | "no" | "0" => Ok(false),
+            _ => Err(self.invalid(column, text, "expected true/false, yes/no or 1/0")),
+        }
+    }
+
+    fn cell(&self, column: &str) -> Result<&'a str, CellParseError> {
+        self.get(column)
+            .ok_or_else(|| CellParseError::MissingColumn {
+                column: column.to_string(),
+                line: self.line,
+            })
+    }
+
+    fn invalid(&self, column: &str, text: &str, reason: impl Display) -> CellParseError {
+        CellParseError::Invalid {
+            column: column.to_string(),
+            line: self.line,
+            text: text.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
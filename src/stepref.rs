@@ -0,0 +1,267 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::hash::StableHasher;
+use crate::{Feature, Rule, Scenario, Step};
+
+/// An occurrence of a step in a feature file, as returned by [`Feature::step_ref`], for
+/// registries of step definitions kept outside the feature files.
+///
+/// It is written and read back as `path:scenario:index`, with the scenario id in hex and the
+/// path and its `:` left out when the feature has none.
+///
+/// | Identifier | Changes when |
+/// | --- | --- |
+/// | [`Step::fingerprint`] | the step's type or words change, but not its spacing or position |
+/// | [`Scenario::stable_id`] | the scenario is renamed, or for an expansion, uses another row |
+/// | [`Feature::scenario_id`] | the stable id or rule changes, or a namesake is added before it |
+/// | `StepRefId` | the path or scenario id changes, or steps are added or removed before it |
+///
+/// Editing other scenarios, reordering those with other names, or editing other steps without
+/// adding or removing any, changes none of them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StepRefId {
+    /// The path of the feature file, if known.
+    pub path: Option<PathBuf>,
+    /// The [`scenario_id`](Feature::scenario_id) of the scenario.
+    pub scenario: u64,
+    /// The index of the step among the scenario's own steps, not counting the background.
+    /// Sub-steps have no index of their own, and are referred to by their step's.
+    pub index: usize,
+}
+
+/// The error reading a [`StepRefId`] that is not in the form `path:scenario:index`
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Not a step reference: {0:?}")]
+pub struct InvalidStepRef(pub String);
+
+impl Display for StepRefId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        write!(f, "{:016x}:{}", self.scenario, self.index)
+    }
+}
+
+impl FromStr for StepRefId {
+    type Err = InvalidStepRef;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidStepRef(s.to_string());
+        let mut parts = s.rsplitn(3, ':');
+
+        let index = parts
+            .next()
+            .and_then(|i| i.parse().ok())
+            .ok_or_else(invalid)?;
+        let scenario = parts
+            .next()
+            .and_then(|id| u64::from_str_radix(id, 16).ok())
+            .ok_or_else(invalid)?;
+
+        Ok(StepRefId {
+            path: parts.next().map(PathBuf::from),
+            scenario,
+            index,
+        })
+    }
+}
+
+impl Step {
+    /// A hash of the step's resolved type and its value with runs of whitespace collapsed to
+    /// single spaces, stable across runs and platforms.
+    ///
+    /// The keyword as written is not included, so `And` and `Given` continuing a given step
+    /// have the same fingerprint, nor are the step's docstring or table.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        self.ty.hash(&mut hasher);
+        for word in self.value.split_whitespace() {
+            word.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+impl Scenario {
+    /// A hash of the scenario's name and, for a scenario expanded from an outline, the
+    /// [`example_index`](Scenario::example_index) of the row it was expanded from, stable
+    /// across runs and platforms.
+    ///
+    /// Scenarios with the same name in one feature have the same id, which
+    /// [`Feature::scenario_id`] tells apart. Moving a scenario, or editing the lines above it,
+    /// does not change its id or those of its expansions.
+    pub fn stable_id(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        (&self.name, self.example_index).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl Feature {
+    /// The identifier of the step at `index` in `scenario`'s own steps, which is one of the
+    /// feature's scenarios or an expansion of one.
+    pub fn step_ref(&self, scenario: &Scenario, index: usize) -> StepRefId {
+        StepRefId {
+            path: self.path.clone(),
+            scenario: self.scenario_id(scenario),
+            index,
+        }
+    }
+
+    /// A hash of `scenario`'s [`stable_id`](Scenario::stable_id), the name of the rule
+    /// containing it and the number of scenarios of the same name before it in that rule, or
+    /// in the feature if it is in none, stable across runs and platforms. Unlike the stable
+    /// id, it tells apart scenarios of the same name in one feature.
+    ///
+    /// `scenario` is one of the feature's scenarios or a copy or expansion of one, found by
+    /// identity or else by its span. One that cannot be found, such as a copy with its
+    /// positions stripped, is taken to be in no rule and the first of its name.
+    pub fn scenario_id(&self, scenario: &Scenario) -> u64 {
+        let (rule, ordinal) = self.locate(scenario).unwrap_or((None, 0));
+        let mut hasher = StableHasher::default();
+        (scenario.stable_id(), rule.map(|r| &r.name), ordinal).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The rule containing `scenario` and the number of scenarios before it there with the
+    /// same name.
+    fn locate(&self, scenario: &Scenario) -> Option<(Option<&Rule>, usize)> {
+        let is_source = |s: &Scenario| {
+            std::ptr::eq(s, scenario) || (scenario.span != (0, 0) && s.span == scenario.span)
+        };
+        let top = std::iter::once((None, self.scenarios.iter().collect::<Vec<_>>()));
+        let nested = self
+            .rules
+            .iter()
+            .map(|r| (Some(r), r.scenarios().collect::<Vec<_>>()));
+
+        top.chain(nested).find_map(|(rule, siblings)| {
+            let i = siblings.iter().position(|s| is_source(s))?;
+            let name = &siblings[i].name;
+            Some((
+                rule,
+                siblings[..i].iter().filter(|s| &s.name == name).count(),
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r"Feature: Accounts
+  Scenario: Opening
+    Given a customer
+    When they open an account
+    Then it is empty
+
+  Scenario: Closing
+    Given an account
+";
+
+    #[test]
+    fn fingerprints_ignore_spacing_and_conjunctions() {
+        let feature = Feature::parse(
+            "Feature: A\n  Scenario: B\n    Given a   customer\n    And a customer\n    Then a customer\n",
+        )
+        .unwrap();
+        let steps = &feature.scenarios[0].steps;
+
+        assert_eq!(steps[0].fingerprint(), steps[1].fingerprint());
+        assert_ne!(steps[0].fingerprint(), steps[2].fingerprint());
+    }
+
+    #[test]
+    fn step_refs_survive_unrelated_edits() {
+        let mut feature = Feature::parse(SOURCE).unwrap();
+        feature.path = Some(PathBuf::from("features/accounts.feature"));
+        let before = feature.step_ref(&feature.scenarios[0], 1);
+
+        feature.scenarios[0].steps[0].value = "a new customer".to_string();
+        feature.scenarios[1].steps[0].value = "a closed account".to_string();
+        feature.scenarios.swap(0, 1);
+        assert_eq!(feature.step_ref(&feature.scenarios[1], 1), before);
+
+        feature.scenarios[1].name = "Opening an account".to_string();
+        assert_ne!(feature.step_ref(&feature.scenarios[1], 1), before);
+    }
+
+    #[test]
+    fn step_refs_round_trip_as_text() {
+        let mut feature = Feature::parse(SOURCE).unwrap();
+        let without_path = feature.step_ref(&feature.scenarios[1], 0);
+        feature.path = Some(PathBuf::from("C:/features/accounts.feature"));
+        let with_path = feature.step_ref(&feature.scenarios[1], 0);
+
+        for id in [without_path, with_path] {
+            assert_eq!(id.to_string().parse(), Ok(id));
+        }
+        assert_eq!(
+            "accounts.feature:zz:1".parse::<StepRefId>(),
+            Err(InvalidStepRef("accounts.feature:zz:1".to_string()))
+        );
+    }
+
+    #[test]
+    fn expanded_scenarios_have_their_own_ids() {
+        let feature = Feature::parse(
+            "Feature: A\n  Scenario Outline: B\n    Given <n>\n\n    Examples:\n      | n |\n      | 1 |\n      | 2 |\n",
+        )
+        .unwrap();
+        let expanded = feature.scenarios[0].expand_examples();
+
+        assert_ne!(expanded[0].stable_id(), expanded[1].stable_id());
+        assert_ne!(expanded[0].stable_id(), feature.scenarios[0].stable_id());
+
+        let moved = Feature::parse(
+            "Feature: A\n\n  Scenario Outline: B\n    Given <n>\n\n    Examples:\n      | n |\n      | 1 |\n",
+        )
+        .unwrap();
+        let moved = moved.scenarios[0].expand_examples();
+        assert_eq!(moved[0].stable_id(), expanded[0].stable_id());
+        assert_ne!(moved[0].example_row, expanded[0].example_row);
+    }
+
+    #[test]
+    fn same_named_scenarios_have_their_own_step_refs() {
+        let feature = Feature::parse(
+            r"Feature: A
+  Scenario: S
+    Given a
+
+  Scenario: S
+    Given a
+
+  Rule: R
+    Scenario: S
+      Given a
+",
+        )
+        .unwrap();
+        let rule_scenario = feature.rules[0].scenarios().next().unwrap();
+        let refs = [
+            feature.step_ref(&feature.scenarios[0], 0),
+            feature.step_ref(&feature.scenarios[1], 0),
+            feature.step_ref(rule_scenario, 0),
+        ];
+
+        assert_eq!(feature.scenarios[0].stable_id(), rule_scenario.stable_id());
+        assert_ne!(refs[0], refs[1]);
+        assert_ne!(refs[0], refs[2]);
+        assert_ne!(refs[1], refs[2]);
+        assert_eq!(feature.step_ref(&feature.scenarios[1].clone(), 0), refs[1]);
+    }
+}
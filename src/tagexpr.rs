@@ -42,6 +42,18 @@ pub enum TagOperation {
     Tag(String),
 }
 
+impl TagOperation {
+    /// Whether the expression holds for a set of tags, given without their leading `@`.
+    pub fn matches<S: AsRef<str>>(&self, tags: &[S]) -> bool {
+        match self {
+            TagOperation::And(a, b) => a.matches(tags) && b.matches(tags),
+            TagOperation::Or(a, b) => a.matches(tags) || b.matches(tags),
+            TagOperation::Not(a) => !a.matches(tags),
+            TagOperation::Tag(t) => tags.iter().any(|x| x.as_ref() == t),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,306 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::dialect::{find_keyword, Dialect};
+
+/// The classification of a line of a .feature file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Empty,
+    Comment,
+    Language,
+    TagLine,
+    FeatureLine,
+    RuleLine,
+    BackgroundLine,
+    ScenarioLine,
+    ExamplesLine,
+    StepLine,
+    DocStringSeparator,
+    TableRow,
+    Other,
+    Eof,
+}
+
+/// A line of a .feature file, as classified by [`token_scan`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LineToken {
+    /// The kind of line.
+    pub kind: TokenKind,
+    /// The `(line, col)` position of the first non-whitespace character of the line.
+    pub position: (usize, usize),
+    /// The keyword starting the line, or the delimiter of a docstring separator.
+    pub keyword: Option<String>,
+    /// The text of the line after the keyword, if any.
+    pub text: String,
+    /// The tags of a tag line or the cells of a table row, with the column each starts at.
+    pub items: Vec<(usize, String)>,
+}
+
+impl LineToken {
+    fn new(kind: TokenKind, position: (usize, usize)) -> LineToken {
+        LineToken {
+            kind,
+            position,
+            keyword: None,
+            text: String::new(),
+            items: vec![],
+        }
+    }
+
+    fn keyword(mut self, keyword: &str) -> Self {
+        self.keyword = Some(keyword.to_string());
+        self
+    }
+
+    fn text(mut self, text: &str) -> Self {
+        self.text = text.to_string();
+        self
+    }
+}
+
+/// Formats the token as a line of the reference implementation's `.tokens` test files, such as
+/// `(3:5)StepLine:Given /a step/`.
+impl std::fmt::Display for LineToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.kind == TokenKind::Eof {
+            return write!(f, "EOF");
+        }
+
+        let keyword = self.keyword.as_deref().unwrap_or_default();
+        let space = if self.kind == TokenKind::StepLine {
+            " "
+        } else {
+            ""
+        };
+        let items = self
+            .items
+            .iter()
+            .map(|(col, text)| format!("{}:{}", col, text))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        write!(
+            f,
+            "({}:{}){:?}:{}{}/{}/{}",
+            self.position.0, self.position.1, self.kind, keyword, space, self.text, items
+        )
+    }
+}
+
+/// Classifies each line of `source` without building a [`Feature`](crate::Feature).
+///
+/// Unlike the parser this never fails: lines that are not recognised are [`TokenKind::Other`].
+/// A `# language:` directive before any other content switches the dialect keywords are read
+/// in. The last token is always [`TokenKind::Eof`].
+pub fn token_scan(source: &str) -> Vec<LineToken> {
+    let mut dialect = Dialect::default();
+    let mut docstring: Option<(&str, usize)> = None;
+    let mut seen_content = false;
+    let mut tokens = vec![];
+
+    let source = source.strip_suffix('\n').unwrap_or(source);
+    let lines = if source.is_empty() {
+        vec![]
+    } else {
+        source.split('\n').collect()
+    };
+
+    for (i, line) in lines.into_iter().enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let trimmed = line.trim_start();
+        let indent = line.chars().count() - trimmed.chars().count();
+        let position = (i + 1, indent + 1);
+        let trimmed = trimmed.trim_end();
+
+        if let Some((delimiter, width)) = docstring {
+            if trimmed.starts_with(delimiter) {
+                docstring = None;
+                tokens.push(
+                    LineToken::new(TokenKind::DocStringSeparator, position).keyword(delimiter),
+                );
+            } else {
+                let text = line
+                    .char_indices()
+                    .nth(indent.min(width))
+                    .map_or("", |(i, _)| &line[i..]);
+                tokens.push(LineToken::new(TokenKind::Other, (i + 1, 1)).text(text));
+            }
+            continue;
+        }
+
+        let token = if trimmed.is_empty() {
+            LineToken::new(TokenKind::Empty, (i + 1, 1))
+        } else if let Some(comment) = trimmed.strip_prefix('#') {
+            match language(comment).filter(|_| !seen_content) {
+                Some(code) => {
+                    if let Some(d) = Dialect::from_language(code) {
+                        dialect = d;
+                    }
+                    LineToken::new(TokenKind::Language, position).text(code)
+                }
+                None => LineToken::new(TokenKind::Comment, (i + 1, 1)).text(line.trim_end()),
+            }
+        } else if trimmed.starts_with('@') {
+            let mut token = LineToken::new(TokenKind::TagLine, position);
+            token.items = items(line, line.split_whitespace());
+            token
+        } else if let Some(row) = trimmed.strip_prefix('|') {
+            let mut token = LineToken::new(TokenKind::TableRow, position);
+            let cells = row.split('|').map(str::trim);
+            let count = row.matches('|').count();
+            token.items = items(line, cells.take(count));
+            token
+        } else if let Some(delimiter) = ["\"\"\"", "```"].iter().find(|d| trimmed.starts_with(**d))
+        {
+            docstring = Some((delimiter, indent));
+            LineToken::new(TokenKind::DocStringSeparator, position)
+                .keyword(delimiter)
+                .text(trimmed[delimiter.len()..].trim())
+        } else {
+            keyword_line(&dialect, trimmed, position)
+        };
+
+        if !matches!(token.kind, TokenKind::Empty | TokenKind::Comment) {
+            seen_content = true;
+        }
+        tokens.push(token);
+    }
+
+    tokens.push(LineToken::new(TokenKind::Eof, (tokens.len() + 1, 1)));
+    tokens
+}
+
+/// The language code of a `# language:` comment, given the text after the `#`.
+fn language(comment: &str) -> Option<&str> {
+    let code = comment.trim_start().strip_prefix("language")?;
+    let code = code.trim_start().strip_prefix(':')?.trim();
+    Some(code).filter(|c| !c.is_empty() && !c.contains(char::is_whitespace))
+}
+
+/// Pairs each of `parts` with the 1-based column it is found at in `line`, searching from the
+/// end of the previous part. An empty table cell is placed just after its opening `|`.
+fn items<'a>(line: &str, parts: impl Iterator<Item = &'a str>) -> Vec<(usize, String)> {
+    let mut offset = 0;
+
+    parts
+        .map(|part| {
+            let start = if part.is_empty() {
+                offset + line[offset..].find('|').map_or(0, |i| i + 1)
+            } else {
+                offset + line[offset..].find(part).unwrap_or(0)
+            };
+            offset = start + part.len();
+            (line[..start].chars().count() + 1, part.to_string())
+        })
+        .collect()
+}
+
+fn keyword_line(dialect: &Dialect, line: &str, position: (usize, usize)) -> LineToken {
+    let keywords = &dialect.keywords;
+    let blocks = [
+        (keywords.feature, TokenKind::FeatureLine),
+        (keywords.rule, TokenKind::RuleLine),
+        (keywords.background, TokenKind::BackgroundLine),
+        (keywords.scenario, TokenKind::ScenarioLine),
+        (keywords.scenario_outline, TokenKind::ScenarioLine),
+        (keywords.examples, TokenKind::ExamplesLine),
+    ];
+
+    for (list, kind) in blocks.iter() {
+        if let Some(k) = find_keyword(list, line, false) {
+            if let Some(rest) = line[k.len()..].strip_prefix([':', '：']) {
+                return LineToken::new(*kind, position).keyword(k).text(rest.trim());
+            }
+        }
+    }
+
+    let steps = [
+        keywords.given,
+        keywords.when,
+        keywords.then,
+        keywords.and,
+        keywords.but,
+    ];
+
+    for list in steps.iter() {
+        if let Some(k) = find_keyword(list, line, false) {
+            let rest = &line[k.len()..];
+            if rest.starts_with(char::is_whitespace) {
+                return LineToken::new(TokenKind::StepLine, position)
+                    .keyword(k)
+                    .text(rest.trim_start());
+            }
+        }
+    }
+
+    LineToken::new(TokenKind::Other, position).text(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scans_lines() {
+        let source = r#"# language: en
+@web @smoke
+Feature: Scanning
+  A description
+
+  # a comment
+  Scenario Outline: Lines
+    Given a <thing>
+      """text
+      one
+        two
+      """
+    But nothing else
+      | a | | c |
+
+    Examples:
+      | thing |
+"#;
+        let tokens = token_scan(source)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                "(1:1)Language:/en/",
+                "(2:1)TagLine://1:@web,6:@smoke",
+                "(3:1)FeatureLine:Feature/Scanning/",
+                "(4:3)Other:/A description/",
+                "(5:1)Empty://",
+                "(6:1)Comment:/  # a comment/",
+                "(7:3)ScenarioLine:Scenario Outline/Lines/",
+                "(8:5)StepLine:Given /a <thing>/",
+                "(9:7)DocStringSeparator:\"\"\"/text/",
+                "(10:1)Other:/one/",
+                "(11:1)Other:/  two/",
+                "(12:7)DocStringSeparator:\"\"\"//",
+                "(13:5)StepLine:But /nothing else/",
+                "(14:7)TableRow://9:a,12:,15:c",
+                "(15:1)Empty://",
+                "(16:5)ExamplesLine:Examples//",
+                "(17:7)TableRow://9:thing",
+                "EOF",
+            ]
+        );
+    }
+
+    #[test]
+    fn language_switches_keywords() {
+        let tokens = token_scan("# language: de\nFunktionalität: Gurken\n  Angenommen ich\n");
+        assert_eq!(tokens[1].kind, TokenKind::FeatureLine);
+        assert_eq!(tokens[2].kind, TokenKind::StepLine);
+        assert_eq!(tokens[2].keyword.as_deref(), Some("Angenommen"));
+    }
+}
@@ -0,0 +1,549 @@
+// Copyright (c) 2020  Brendan Molloy <brendan@bbqsrc.net>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io;
+
+use crate::{
+    Background, DocstringDelimiter, Examples, Feature, FeatureChild, Rule, RuleChild, Scenario,
+    Step, Table,
+};
+
+const INDENT: usize = 2;
+
+/// Tables whose aligned rows would be wider than this many characters are written unaligned.
+const MAX_ALIGNED_WIDTH: usize = 1000;
+
+/// Options controlling how [`Feature::to_gherkin_string`] renders a feature.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Reproduce the number of blank lines found before each block and step when the feature
+    /// was parsed (see `leading_blank_lines`), instead of using the canonical spacing.
+    pub preserve_blank_lines: bool,
+    /// Whether to end the output with a newline, or `None` to end it as the parsed file ended
+    /// (see `trailing_newline`).
+    pub trailing_newline: Option<bool>,
+}
+
+impl Feature {
+    /// Renders the feature as Gherkin source.
+    ///
+    /// The output is indented by two spaces per level with aligned tables, apart from tables
+    /// too wide to read aligned, whose cells are written with single spaces. Comments are not
+    /// retained by the parser and so are not written, apart from those kept with table rows, and
+    /// block keywords are written in English.
+    pub fn to_gherkin_string(&self, options: &FormatOptions) -> String {
+        let mut out = vec![];
+        self.write_to(&mut out, options)
+            .expect("writing to a Vec cannot fail");
+        String::from_utf8(out).expect("rendered features are UTF-8")
+    }
+
+    /// Renders the feature as Gherkin source into `w`, a line at a time, as
+    /// [`to_gherkin_string`](Feature::to_gherkin_string) would. Errors from `w` are returned
+    /// as they occur, leaving the output incomplete.
+    pub fn write_to<W: io::Write>(&self, w: &mut W, options: &FormatOptions) -> io::Result<()> {
+        let mut writer = Writer {
+            out: w,
+            options,
+            newline: self.line_ending.as_str(),
+            pending_newline: false,
+        };
+        writer.feature(self)?;
+
+        if options.trailing_newline.unwrap_or(self.trailing_newline) {
+            writer.end_line()?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Writer<'a, W> {
+    out: &'a mut W,
+    options: &'a FormatOptions,
+    newline: &'static str,
+    /// Whether the last line written still needs its newline. It is held back so a feature
+    /// without a final newline can be written without one.
+    pending_newline: bool,
+}
+
+impl<W: io::Write> Writer<'_, W> {
+    fn end_line(&mut self) -> io::Result<()> {
+        if self.pending_newline {
+            self.out.write_all(self.newline.as_bytes())?;
+        }
+        self.pending_newline = false;
+        Ok(())
+    }
+
+    fn line(&mut self, indent: usize, text: &str) -> io::Result<()> {
+        self.end_line()?;
+        if !text.is_empty() {
+            write!(self.out, "{:indent$}{}", "", text, indent = indent)?;
+        }
+        self.pending_newline = true;
+        Ok(())
+    }
+
+    fn blank_lines(&mut self, canonical: usize, leading: usize) -> io::Result<()> {
+        let count = if self.options.preserve_blank_lines {
+            leading
+        } else {
+            canonical
+        };
+
+        for _ in 0..count {
+            self.line(0, "")?;
+        }
+
+        Ok(())
+    }
+
+    fn tags(&mut self, indent: usize, tags: &[String]) -> io::Result<()> {
+        if !tags.is_empty() {
+            let tags = tags.iter().map(|t| format!("@{}", t)).collect::<Vec<_>>();
+            self.line(indent, &tags.join(" "))?;
+        }
+
+        Ok(())
+    }
+
+    fn feature(&mut self, feature: &Feature) -> io::Result<()> {
+        self.tags(0, &feature.tags)?;
+        self.line(0, &format!("Feature: {}", feature.name))?;
+
+        if let Some(description) = &feature.description {
+            for line in description.lines() {
+                self.line(INDENT, line)?;
+            }
+        }
+
+        for child in feature.children() {
+            match child {
+                FeatureChild::Background(background) => self.background(INDENT, background)?,
+                FeatureChild::Rule(rule) => self.rule(INDENT, rule)?,
+                FeatureChild::Scenario(scenario) => self.scenario(INDENT, scenario)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn background(&mut self, indent: usize, background: &Background) -> io::Result<()> {
+        self.blank_lines(1, background.leading_blank_lines)?;
+        self.line(indent, "Background:")?;
+
+        if let Some(description) = &background.description {
+            for line in description.lines() {
+                self.line(indent + INDENT, line)?;
+            }
+        }
+
+        self.steps(indent + INDENT, &background.steps)
+    }
+
+    fn rule(&mut self, indent: usize, rule: &Rule) -> io::Result<()> {
+        self.blank_lines(1, rule.leading_blank_lines)?;
+        self.tags(indent, &rule.tags)?;
+        self.line(indent, &format!("Rule: {}", rule.name))?;
+
+        if let Some(description) = &rule.description {
+            for line in description.lines() {
+                self.line(indent + INDENT, line)?;
+            }
+        }
+
+        for child in rule.children.iter() {
+            match child {
+                RuleChild::Background(background) => {
+                    self.background(indent + INDENT, background)?
+                }
+                RuleChild::Scenario(scenario) => self.scenario(indent + INDENT, scenario)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn scenario(&mut self, indent: usize, scenario: &Scenario) -> io::Result<()> {
+        let keyword = if scenario.outline || !scenario.examples.is_empty() {
+            "Scenario Outline"
+        } else {
+            "Scenario"
+        };
+
+        self.blank_lines(1, scenario.leading_blank_lines)?;
+        self.tags(indent, &scenario.tags)?;
+        self.line(indent, &format!("{}: {}", keyword, scenario.name))?;
+        self.steps(indent + INDENT, &scenario.steps)?;
+
+        for examples in scenario.examples.iter() {
+            self.examples(indent + INDENT, examples)?;
+        }
+
+        Ok(())
+    }
+
+    fn examples(&mut self, indent: usize, examples: &Examples) -> io::Result<()> {
+        self.blank_lines(1, examples.leading_blank_lines)?;
+        self.tags(indent, &examples.tags)?;
+        self.line(indent, "Examples:")?;
+        if let Some(table) = &examples.table {
+            self.table(indent + INDENT, table)?;
+        }
+
+        Ok(())
+    }
+
+    fn steps(&mut self, indent: usize, steps: &[Step]) -> io::Result<()> {
+        for step in steps {
+            self.blank_lines(0, step.leading_blank_lines)?;
+            self.line(indent, &step.to_string())?;
+
+            if let Some(docstring) = &step.docstring {
+                self.docstring(indent + INDENT, docstring, step.docstring_delimiter)?;
+            }
+
+            if let Some(table) = &step.table {
+                self.table(indent + INDENT, table)?;
+            }
+
+            self.steps(indent + INDENT, &step.sub_steps)?;
+        }
+
+        Ok(())
+    }
+
+    fn docstring(
+        &mut self,
+        indent: usize,
+        docstring: &str,
+        delimiter: DocstringDelimiter,
+    ) -> io::Result<()> {
+        // Content containing the delimiter would end the docstring early, so the other one is
+        // used instead, or the delimiter escaped if the content contains both.
+        let delimiter = if docstring.contains(delimiter.as_str())
+            && !docstring.contains(delimiter.other().as_str())
+        {
+            delimiter.other()
+        } else {
+            delimiter
+        };
+        let docstring = docstring.replace(delimiter.as_str(), delimiter.escaped());
+
+        // The parsed content keeps the text following the opening delimiter and preceding the
+        // closing one, so the delimiters are written around it rather than on their own lines.
+        let text = format!("{0}{1}{0}", delimiter.as_str(), docstring);
+        for line in text.split('\n') {
+            self.line(indent, line)?;
+        }
+
+        Ok(())
+    }
+
+    fn table(&mut self, indent: usize, table: &Table) -> io::Result<()> {
+        let rows: Vec<Vec<_>> = table
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|c| escape_cell(c)).collect())
+            .collect();
+
+        let mut widths = vec![0; table.row_width()];
+        for row in rows.iter() {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        if widths.iter().map(|w| w + 3).sum::<usize>() > MAX_ALIGNED_WIDTH {
+            widths.iter_mut().for_each(|w| *w = 0);
+        }
+
+        for (i, row) in rows.iter().enumerate() {
+            for comment in table.row_leading_comments.get(i).into_iter().flatten() {
+                self.line(indent, &format!("# {}", comment))?;
+            }
+
+            let mut line = String::from("|");
+            for (width, cell) in widths.iter().zip(row.iter()) {
+                line.push(' ');
+                line.push_str(cell);
+                line.extend(std::iter::repeat_n(
+                    ' ',
+                    width.saturating_sub(cell.chars().count()),
+                ));
+                line.push_str(" |");
+            }
+            if let Some(Some(comment)) = table.row_comments.get(i) {
+                line.push_str(" # ");
+                line.push_str(comment);
+            }
+            self.line(indent, &line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A table cell as written between pipes, with pipes, backslashes and newlines escaped so it
+/// is read back unchanged.
+fn escape_cell(cell: &str) -> String {
+    let mut out = String::with_capacity(cell.len());
+    for c in cell.chars() {
+        match c {
+            '|' => out.push_str("\\|"),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPACED: &str = r#"@billing
+Feature: Invoices
+  Invoices are sent monthly.
+
+  Background:
+    Given a customer
+
+  @smoke
+  Scenario: Paying an invoice
+    Given an invoice
+    And a card
+
+    When the invoice is paid
+
+
+    Then a receipt is sent
+      """
+      Thanks!
+      """
+
+  Scenario Outline: Totals
+    Given <count> items
+      | name  | price |
+      | apple | 1     |
+
+    Examples:
+      | count |
+      | 2     |
+"#;
+
+    #[test]
+    fn trailing_newline_is_kept() {
+        let with = "Feature: Newlines\n\n  Scenario: A\n    Given a step\n";
+        let without = with.trim_end_matches('\n');
+
+        for source in [with, without].iter() {
+            let feature = Feature::parse(source).unwrap();
+            assert_eq!(feature.trailing_newline, source.ends_with('\n'));
+            assert_eq!(
+                feature.to_gherkin_string(&FormatOptions::default()),
+                *source
+            );
+        }
+    }
+
+    /// Accepts a fixed number of bytes, then fails.
+    struct Full(usize);
+
+    impl io::Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 < buf.len() {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "full"));
+            }
+            self.0 -= buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trailing_newline_can_be_normalized() {
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+
+        for name in ["trailing_newline", "no_trailing_newline"].iter() {
+            let path = fixtures.join(name).with_extension("feature");
+            let feature = Feature::parse_path(&path).unwrap();
+
+            for &ends in [true, false].iter() {
+                let options = FormatOptions {
+                    trailing_newline: Some(ends),
+                    ..FormatOptions::default()
+                };
+                let out = feature.to_gherkin_string(&options);
+                assert_eq!(out.ends_with('\n'), ends);
+                assert_eq!(
+                    out.trim_end(),
+                    feature
+                        .to_gherkin_string(&FormatOptions::default())
+                        .trim_end()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn write_to_streams_and_propagates_errors() {
+        let feature = Feature::parse(SPACED).unwrap();
+        let options = FormatOptions::default();
+
+        let mut out = vec![];
+        feature.write_to(&mut out, &options).unwrap();
+        assert_eq!(out, feature.to_gherkin_string(&options).into_bytes());
+
+        let err = feature.write_to(&mut Full(40), &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn docstring_delimiters_are_kept_or_switched_when_unsafe() {
+        let source = "Feature: Fences\n  Scenario: A\n    Given code\n      ```rust\n      let s = \"\\`\\`\\`\";\n      ```\n    And a quote\n      \"\"\"\n      plain\n      \"\"\"\n";
+        let mut feature = Feature::parse(source).unwrap();
+        let steps = &feature.scenarios[0].steps;
+        assert_eq!(steps[0].docstring_delimiter, DocstringDelimiter::Backticks);
+        assert_eq!(
+            steps[0].docstring.as_deref(),
+            Some("rust\nlet s = \"```\";\n")
+        );
+        assert_eq!(steps[1].docstring_delimiter, DocstringDelimiter::Quotes);
+
+        let options = FormatOptions::default();
+        let out = feature.to_gherkin_string(&options);
+        assert!(out.contains("      \"\"\"rust\n      let s = \"```\";\n      \"\"\"\n"));
+        assert!(out.contains("      \"\"\"\n      plain\n      \"\"\"\n"));
+
+        let both = "\nsay \"\"\" or ```\n";
+        feature.scenarios[0].steps[1].docstring = Some("\nsay \"\"\"\n".to_string());
+        feature.scenarios[0].steps[0].docstring = Some(both.to_string());
+        let out = feature.to_gherkin_string(&options);
+        assert!(out.contains("      ```\n      say \"\"\"\n      ```\n"));
+        assert!(out.contains("      say \"\"\" or \\`\\`\\`\n"));
+
+        let reparsed = Feature::parse(&out).unwrap();
+        let docstrings: Vec<_> = reparsed.scenarios[0]
+            .steps
+            .iter()
+            .map(|s| s.docstring.as_deref().unwrap())
+            .collect();
+        assert_eq!(docstrings, vec![both, "\nsay \"\"\"\n"]);
+    }
+
+    #[test]
+    fn table_cells_are_escaped() {
+        let source = "Feature: Escapes\n  Scenario: A\n    Given a table\n      | a\\|b | C:\\\\new | one\\ntwo | \\x |\n";
+        let mut feature = Feature::parse(source).unwrap();
+        let table = feature.scenarios[0].steps[0].table.as_mut().unwrap();
+        assert_eq!(table.rows[0], vec!["a|b", "C:\\new", "one\ntwo", "\\x"]);
+
+        table.rows[0][3] = "|".to_string();
+        let out = feature.to_gherkin_string(&FormatOptions::default());
+        assert!(out.contains("      | a\\|b | C:\\\\new | one\\ntwo | \\| |\n"));
+    }
+
+    #[test]
+    fn wide_tables_are_not_aligned() {
+        let mut feature = Feature::parse(
+            "Feature: A\n  Scenario: B\n    Given c\n      | a | b |\n      | 1 | 2 |\n",
+        )
+        .unwrap();
+        let table = feature.scenarios[0].steps[0].table.as_mut().unwrap();
+        table.rows[0][0] = "x".repeat(MAX_ALIGNED_WIDTH);
+
+        let out = feature.to_gherkin_string(&FormatOptions::default());
+        assert!(out.ends_with(" | b |\n      | 1 | 2 |\n"));
+        let reparsed = Feature::parse(out).unwrap();
+        assert_eq!(
+            reparsed.scenarios[0].steps[0].table.as_ref().unwrap().rows,
+            feature.scenarios[0].steps[0].table.as_ref().unwrap().rows
+        );
+    }
+
+    #[test]
+    fn random_table_cells_round_trip() {
+        // A fixed xorshift sequence, so failures are reproducible.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % n as u64) as usize
+        };
+        let alphabet = ['a', 'n', ' ', '|', '\\', '\n', '#', '"', '<', 'é'];
+
+        for _ in 0..200 {
+            let width = 1 + next(4);
+            let rows: Vec<Vec<String>> = (0..1 + next(4))
+                .map(|_| {
+                    (0..width)
+                        .map(|_| {
+                            let cell: String = (0..next(8)).map(|_| alphabet[next(10)]).collect();
+                            // Whitespace around a cell is not part of its value.
+                            cell.trim_matches(' ').to_string()
+                        })
+                        .collect()
+                })
+                .collect();
+
+            let mut feature =
+                Feature::parse("Feature: A\n  Scenario: B\n    Given a table\n      | x |\n")
+                    .unwrap();
+            feature.scenarios[0].steps[0].table = Some(Table::builder().rows(rows.clone()).build());
+
+            let out = feature.to_gherkin_string(&FormatOptions::default());
+            let reparsed = Feature::parse(&out).unwrap_or_else(|e| panic!("{}\n{}", e, out));
+            let table = reparsed.scenarios[0].steps[0].table.as_ref();
+            assert_eq!(table.map(|t| &t.rows), Some(&rows), "{}", out);
+        }
+    }
+
+    #[test]
+    fn canonical_output() {
+        let feature = Feature::parse(SPACED).unwrap();
+        let out = feature.to_gherkin_string(&FormatOptions::default());
+        assert!(out.contains("    And a card\n    When the invoice is paid\n    Then"));
+        let reparsed = Feature::parse(&out).unwrap();
+        assert_eq!(reparsed.to_gherkin_string(&FormatOptions::default()), out);
+    }
+
+    #[test]
+    fn single_step_edit_is_a_single_line_diff() {
+        let mut feature = Feature::parse(SPACED).unwrap();
+        feature.scenarios[0].steps[2].value = "the invoice is refunded".to_string();
+
+        let options = FormatOptions {
+            preserve_blank_lines: true,
+            ..FormatOptions::default()
+        };
+        let out = feature.to_gherkin_string(&options);
+
+        let before = SPACED.lines().collect::<Vec<_>>();
+        let after = out.lines().collect::<Vec<_>>();
+        assert_eq!(before.len(), after.len());
+
+        let changed = before
+            .iter()
+            .zip(after.iter())
+            .filter(|(a, b)| a != b)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changed,
+            vec![(
+                &"    When the invoice is paid",
+                &"    When the invoice is refunded"
+            )]
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use gherkin_rust::{compat, Feature, StepType};
+
+/// Drives the features run by the `cucumber` test through the conversion, as a runner would.
+#[test]
+fn converts_runner_features() {
+    let mut paths: Vec<_> = std::fs::read_dir("tests/features")
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "feature"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    for path in paths {
+        let feature = Feature::parse_path(&path).unwrap();
+        let pickles = compat::pickles(&feature);
+
+        assert_eq!(pickles.len(), feature.scenarios.len());
+        for pickle in pickles {
+            assert_eq!(pickle.path.as_deref(), Some(&*path));
+            assert_eq!(pickle.feature, feature.name);
+            assert!(pickle.scenario.examples.is_empty());
+        }
+    }
+
+    let feature = Feature::parse_path("tests/features/main.feature").unwrap();
+    let pickle = &compat::pickles(&feature)[0];
+    assert_eq!(pickle.scenario.name, "Nothing");
+    assert_eq!(pickle.scenario.steps[0].ty, StepType::Given);
+    assert_eq!(pickle.scenario.steps[0].value, "I just started");
+}
+
+#[test]
+fn converts_fixtures() {
+    let rules = Feature::parse_path("tests/fixtures/rule_backgrounds.feature").unwrap();
+    let names: Vec<_> = compat::pickles(&rules)
+        .into_iter()
+        .map(|p| (p.rule.unwrap(), p.scenario.name, p.scenario.steps.len()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("Background first".to_string(), "Buying".to_string(), 3),
+            (
+                "Background between scenarios".to_string(),
+                "Browsing".to_string(),
+                3
+            ),
+            (
+                "Background between scenarios".to_string(),
+                "Leaving".to_string(),
+                3
+            ),
+        ]
+    );
+
+    let outline = Feature::parse_path("tests/fixtures/outline.feature").unwrap();
+    let pickles = compat::pickles(&outline);
+    assert_eq!(pickles.len(), 2);
+    assert_eq!(
+        pickles[1].scenario.steps[2].value,
+        "I should have 15 cucumbers"
+    );
+}
@@ -1,15 +1,10 @@
 #[macro_use]
 extern crate cucumber;
-use std::default::Default;
 
+#[derive(Default)]
 pub struct MyWorld {}
 
 impl cucumber::World for MyWorld {}
-impl Default for MyWorld {
-    fn default() -> MyWorld {
-        MyWorld {}
-    }
-}
 
 mod t {
     steps!(crate::MyWorld => {
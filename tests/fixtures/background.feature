@@ -0,0 +1,10 @@
+Feature: Shopping cart
+  Items can be added and removed.
+
+  Background:
+    Given an empty cart
+    And a logged in customer
+
+  Scenario: Adding an item
+    When I add an apple
+    Then the cart holds 1 item
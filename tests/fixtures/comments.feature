@@ -0,0 +1,10 @@
+# A file comment
+@tagged
+Feature: Comments
+  # Before the scenario
+  Scenario: Commented
+    # Between steps
+    Given a step
+
+    # After a blank line
+    Then another step
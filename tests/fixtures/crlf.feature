@@ -0,0 +1,8 @@
+Feature: Windows line endings
+  Scenario: CRLF
+    Given a step
+      """
+      text
+      """
+    Then a table
+      | a | b |
@@ -0,0 +1,22 @@
+Feature: Descriptions in the wild
+  Given the ASCII-art table below, refunds are capped:
+
+  | tier  | cap  |
+  | basic | 100  |
+
+  @finance-team please review the caps.
+  @alice
+  Scenario planning happens quarterly, and the
+  Rule of thumb is: never refund twice.
+  Examples are in the wiki.
+
+  @refunds
+  Scenario: Refunding
+    Given a refund
+
+  Rule: Caps
+    Rules may have | pipes | too,
+    and @mentions.
+
+    Scenario: Capped
+      Given a capped refund
@@ -0,0 +1,7 @@
+Feature: Descriptions
+  A feature description
+  over two lines.
+
+  Scenario: After the description
+    Given a step
+    And another
@@ -0,0 +1,16 @@
+Feature: Docstrings
+  Scenario: Payloads
+    Given a request body
+      """
+      {
+        "id": 1
+      }
+      """
+    And a note
+      """markdown
+      # Heading
+      """
+    And a snippet
+      ```sh
+      echo """quoted"""
+      ```
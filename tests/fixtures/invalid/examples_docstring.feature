@@ -0,0 +1,9 @@
+Feature: Misplaced examples
+  Scenario Outline: Eating
+    Given there are <start> cucumbers
+
+    Examples:
+      """
+      | start |
+      | 12    |
+      """
@@ -0,0 +1,9 @@
+Feature: Mixed line endings
+  Written on three systemsand merged badly
+
+  Scenario: Mixed    Given a payload
+      """      one
+      two
+      """
+    Then a table
+      | a | b |      | 1 | 2 |
@@ -0,0 +1,14 @@
+Feature: Multiple examples
+  Scenario Outline: Logging in
+    Given a <role> user
+    Then they see the <page> page
+
+    @fast
+    Examples:
+      | role  | page  |
+      | admin | admin |
+
+    @slow
+    Examples:
+      | role     | page |
+      | customer | home |
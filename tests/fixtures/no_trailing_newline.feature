@@ -0,0 +1,3 @@
+Feature: No final newline
+  Scenario: A
+    Given a step
\ No newline at end of file
@@ -0,0 +1,20 @@
+Feature: Recovery
+
+  Scenario: Overrun docstring
+    Given a note
+      """
+        Given this is prose
+    When it is sent
+    Then it arrives
+
+  Scenario: Untidy table
+    Given users
+      | name | role  | # the header
+      | ann  | admin |
+      | bob  | user  | oops
+      | cy   | guest |
+
+  Scenario: Unclosed docstring
+    Given a payload
+      ```
+      {"id": 1}
@@ -0,0 +1,21 @@
+Feature: Rule backgrounds
+
+  Background:
+    Given a shop
+
+  Rule: Background first
+    Background:
+      Given a customer
+
+    Scenario: Buying
+      When they buy a thing
+
+  Rule: Background between scenarios
+    Scenario: Browsing
+      When they browse
+
+    Background:
+      Given a guest
+
+    Scenario: Leaving
+      When they leave
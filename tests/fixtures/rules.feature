@@ -0,0 +1,17 @@
+Feature: Rules
+
+  Scenario: Outside any rule
+    Given a step
+
+  @refunds
+  Rule: Refunds need approval
+    Refunds above the limit must be
+    approved by a manager.
+
+    Scenario: Large refund
+      Given a refund of 1000
+      Then a manager must approve it
+
+  Rule: Cancellations
+    Scenario: Cancelling
+      Given an order
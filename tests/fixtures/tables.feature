@@ -0,0 +1,7 @@
+Feature: Tables
+  Scenario: Prices
+    Given the prices
+      | item   | price |
+      | apple  | 1     |
+      | banana |       |
+    Then the total is 1
@@ -0,0 +1,7 @@
+Feature: Tabs
+	Scenario: Indented with tabs
+		Given a step
+			"""
+			one
+			  two
+			"""
@@ -0,0 +1,10 @@
+@billing @v2
+Feature: Tags
+
+  @smoke
+  Scenario: Tagged
+    Given a step
+
+  @wip   @slow
+  Scenario: Spaced tags
+    Given a step
@@ -0,0 +1,5 @@
+Feature: Final newline
+  Scenario: A
+    Given a step
+      | a | b |
+      | 1 | 2 |
@@ -0,0 +1,12 @@
+# language: de
+Funktionalität: Gurken
+  Szenariogrundriss: Essen
+    Gegeben seien <n> Gurken
+    Wenn ich eine esse
+    Und noch eine
+    Dann bleiben weniger übrig
+
+    Beispiele:
+      | n  |
+      | 5  |
+      | 日本 |